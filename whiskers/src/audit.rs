@@ -0,0 +1,202 @@
+use std::fmt::Write as _;
+
+use indexmap::IndexMap;
+
+use crate::{
+    markdown,
+    models::{Color, Palette},
+    simulation::Deficiency,
+};
+
+#[derive(Clone, Copy, Debug, serde::Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    /// WCAG 2 contrast ratio
+    Wcag,
+    /// APCA lightness contrast (Lc)
+    Apca,
+}
+
+impl Algorithm {
+    /// The threshold used when none is given: WCAG AA for body text, or its
+    /// rough APCA equivalent.
+    #[must_use]
+    pub const fn default_threshold(self) -> f64 {
+        match self {
+            Self::Wcag => 4.5,
+            Self::Apca => 60.0,
+        }
+    }
+
+    /// The contrast of `fg` on `bg`. APCA contrast is signed by polarity, so
+    /// its magnitude is used to make it comparable against a threshold.
+    #[must_use]
+    pub fn contrast(self, fg: &Color, bg: &Color) -> f64 {
+        match self {
+            Self::Wcag => fg.contrast_ratio(bg),
+            Self::Apca => fg.apca_contrast(bg).abs(),
+        }
+    }
+}
+
+pub struct Options<'a> {
+    pub algorithm: Algorithm,
+    pub threshold: f64,
+    pub foregrounds: &'a [String],
+    pub backgrounds: &'a [String],
+    /// Colors that must remain distinguishable from each other.
+    pub distinguish: &'a [String],
+    /// Minimum perceptual difference (ΔEOK) between `distinguish` colors.
+    pub min_distance: f64,
+    /// Audit the palette as seen with a color vision deficiency.
+    pub simulation: Option<Deficiency>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Report {
+    pub algorithm: Algorithm,
+    pub threshold: f64,
+    pub simulation: Option<Deficiency>,
+    /// Failing pairs, keyed by flavor identifier.
+    pub flavors: IndexMap<String, Vec<Failure>>,
+    /// Pairs of colors that are too similar to tell apart, keyed by flavor
+    /// identifier.
+    pub indistinguishable: IndexMap<String, Vec<Indistinguishable>>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Indistinguishable {
+    pub a: String,
+    pub b: String,
+    pub distance: f64,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Failure {
+    pub foreground: String,
+    pub background: String,
+    pub contrast: f64,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Flavor {flavor} has no color named {color}")]
+    UnknownColor { flavor: String, color: String },
+}
+
+/// Check every foreground/background pair in every flavor of the palette,
+/// collecting those with a contrast below the threshold.
+pub fn audit(palette: &Palette, options: &Options) -> Result<Report, Error> {
+    let palette = options.simulation.map_or_else(
+        || palette.clone(),
+        |deficiency| palette.simulate(deficiency, false, None),
+    );
+
+    let mut flavors = IndexMap::new();
+    let mut indistinguishable = IndexMap::new();
+    for (identifier, flavor) in &palette {
+        let color = |name: &String| {
+            flavor.colors.get(name).ok_or_else(|| Error::UnknownColor {
+                flavor: identifier.clone(),
+                color: name.clone(),
+            })
+        };
+
+        let mut failures = vec![];
+        for fg in options.foregrounds {
+            for bg in options.backgrounds {
+                let contrast = options.algorithm.contrast(color(fg)?, color(bg)?);
+                if contrast < options.threshold {
+                    failures.push(Failure {
+                        foreground: fg.clone(),
+                        background: bg.clone(),
+                        contrast,
+                    });
+                }
+            }
+        }
+        flavors.insert(identifier.clone(), failures);
+
+        let mut similar = vec![];
+        for (i, a) in options.distinguish.iter().enumerate() {
+            for b in &options.distinguish[i + 1..] {
+                let distance = color(a)?.distance(color(b)?);
+                if distance < options.min_distance {
+                    similar.push(Indistinguishable {
+                        a: a.clone(),
+                        b: b.clone(),
+                        distance,
+                    });
+                }
+            }
+        }
+        indistinguishable.insert(identifier.clone(), similar);
+    }
+
+    Ok(Report {
+        algorithm: options.algorithm,
+        threshold: options.threshold,
+        simulation: options.simulation,
+        flavors,
+        indistinguishable,
+    })
+}
+
+impl Report {
+    #[must_use]
+    pub fn passed(&self) -> bool {
+        self.flavors.values().all(Vec::is_empty)
+            && self.indistinguishable.values().all(Vec::is_empty)
+    }
+
+    #[must_use]
+    pub fn to_markdown(&self, format: markdown::Format) -> String {
+        let mut result = String::new();
+        for (flavor, failures) in &self.flavors {
+            let _ = writeln!(result, "## {flavor}\n");
+            let similar = self.indistinguishable.get(flavor);
+            if let Some(similar) = similar {
+                for pair in similar {
+                    let _ = writeln!(
+                        result,
+                        "- `{}` and `{}` are hard to tell apart: ΔE {:.3}",
+                        pair.a, pair.b, pair.distance
+                    );
+                }
+                if !similar.is_empty() {
+                    result.push('\n');
+                }
+            }
+            if failures.is_empty() {
+                if similar.is_none_or(Vec::is_empty) {
+                    result.push_str("All pairs pass.\n\n");
+                }
+                continue;
+            }
+            match format {
+                markdown::Format::List => {
+                    for failure in failures {
+                        let _ = writeln!(
+                            result,
+                            "- `{}` on `{}`: {:.2}",
+                            failure.foreground, failure.background, failure.contrast
+                        );
+                    }
+                }
+                markdown::Format::Table => {
+                    result.push_str("| Foreground | Background | Contrast |\n");
+                    result.push_str("|------------|------------|----------|\n");
+                    for failure in failures {
+                        let _ = writeln!(
+                            result,
+                            "| `{}` | `{}` | {:.2} |",
+                            failure.foreground, failure.background, failure.contrast
+                        );
+                    }
+                }
+            }
+            result.push('\n');
+        }
+        result
+    }
+}
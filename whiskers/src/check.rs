@@ -0,0 +1,154 @@
+//! comparison of rendered output against the files on disk, for check mode.
+
+use std::path::{Path, PathBuf};
+
+use similar::{ChangeTag, TextDiff};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// The file matches the rendered output.
+    Match,
+    /// The file differs from the rendered output.
+    Mismatch,
+    /// The file doesn't exist.
+    Missing,
+    /// The file exists, but is no longer rendered.
+    Stale,
+    /// The file differed, and was overwritten in update mode.
+    Updated,
+    /// The file was missing, and was written in update mode.
+    Created,
+    /// The file was stale, and was deleted in update mode.
+    Removed,
+}
+
+impl Status {
+    /// Whether the file is as rendered after checking it.
+    #[must_use]
+    pub const fn passed(self) -> bool {
+        !matches!(self, Self::Mismatch | Self::Missing | Self::Stale)
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct File {
+    pub path: PathBuf,
+    pub status: Status,
+}
+
+/// The result of checking every file, so that all problems can be reported
+/// before failing.
+#[derive(Debug, serde::Serialize)]
+pub struct Summary {
+    pub passed: bool,
+    pub files: Vec<File>,
+}
+
+impl Default for Summary {
+    fn default() -> Self {
+        Self {
+            passed: true,
+            files: vec![],
+        }
+    }
+}
+
+impl Summary {
+    pub fn record(&mut self, path: &Path, status: Status) {
+        self.passed &= status.passed();
+        self.files.push(File {
+            path: path.to_path_buf(),
+            status,
+        });
+    }
+
+    /// The number of files with the given status.
+    #[must_use]
+    pub fn count(&self, status: Status) -> usize {
+        self.files.iter().filter(|f| f.status == status).count()
+    }
+}
+
+/// A unified diff from `expected` to `actual`, optionally colorized with ANSI
+/// escape codes.
+///
+/// Output that isn't valid UTF-8 can't be diffed line by line, so only a
+/// short note is returned for it.
+#[must_use]
+pub fn unified_diff(expected: &[u8], actual: &[u8], path: &Path, color: bool) -> String {
+    let (Ok(expected), Ok(actual)) = (std::str::from_utf8(expected), std::str::from_utf8(actual))
+    else {
+        return format!(
+            "Binary files {} and rendered output differ\n",
+            path.display()
+        );
+    };
+
+    let diff = TextDiff::from_lines(expected, actual);
+    let mut out = format!("--- {}\n+++ rendered output\n", path.display());
+    for hunk in diff.unified_diff().context_radius(3).iter_hunks() {
+        let header = hunk.header().to_string();
+        out.push_str(&paint(&header, "36", color));
+        out.push('\n');
+        for change in hunk.iter_changes() {
+            let (sign, code) = match change.tag() {
+                ChangeTag::Delete => ('-', "31"),
+                ChangeTag::Insert => ('+', "32"),
+                ChangeTag::Equal => (' ', ""),
+            };
+            let line = format!("{sign}{}", change.value().trim_end_matches(['\r', '\n']));
+            out.push_str(&paint(&line, code, color && !code.is_empty()));
+            out.push('\n');
+            if change.missing_newline() {
+                out.push_str("\\ No newline at end of file\n");
+            }
+        }
+    }
+    out
+}
+
+fn paint(text: &str, code: &str, color: bool) -> String {
+    if color {
+        format!("\x1b[{code}m{text}\x1b[0m")
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn updated_files_pass() {
+        let mut summary = Summary::default();
+        summary.record(Path::new("a.txt"), Status::Updated);
+        summary.record(Path::new("b.txt"), Status::Created);
+        assert!(summary.passed);
+        summary.record(Path::new("c.txt"), Status::Stale);
+        assert!(!summary.passed);
+    }
+
+    #[test]
+    fn diff_without_color() {
+        let diff = unified_diff(b"a\nb\nc\n", b"a\nB\nc\n", Path::new("x.txt"), false);
+        assert_eq!(
+            diff,
+            "--- x.txt\n+++ rendered output\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n"
+        );
+    }
+
+    #[test]
+    fn diff_with_color() {
+        let diff = unified_diff(b"a\n", b"b\n", Path::new("x.txt"), true);
+        assert!(diff.contains("\x1b[31m-a\x1b[0m\n"));
+        assert!(diff.contains("\x1b[32m+b\x1b[0m\n"));
+    }
+
+    #[test]
+    fn missing_newline() {
+        let diff = unified_diff(b"a", b"a\n", Path::new("x.txt"), false);
+        assert!(diff.ends_with("-a\n\\ No newline at end of file\n+a\n"));
+    }
+}
@@ -3,21 +3,43 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use clap_stdin::FileOrStdin;
 
+pub use crate::models::ColorOverrides;
+use crate::{audit::Algorithm, models::CustomPalette, simulation::Deficiency};
+
 type ValueMap = HashMap<String, serde_json::Value>;
 
 #[derive(Parser, Debug)]
-#[command(version, about)]
+#[allow(clippy::struct_excessive_bools)]
+#[command(
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 pub struct Args {
-    /// Path to the template file, or - for stdin
-    #[arg(required_unless_present = "list_functions")]
-    pub template: Option<FileOrStdin>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
 
-    /// Render a single flavor instead of all four
-    #[arg(long, short)]
-    pub flavor: Option<Flavor>,
+    /// Paths or globs of the template files, or - for stdin
+    ///
+    /// Required unless given in --batch or the configuration file.
+    pub template: Vec<FileOrStdin>,
+
+    /// Also render the templates listed in a JSON or YAML file
+    #[arg(long, value_name = "FILE", value_parser = batch_file)]
+    pub batch: Option<Batch>,
+
+    /// Render a single flavor instead of all of them, which can also be one
+    /// from `--palette`
+    #[arg(long, short, value_parser = FlavorParser)]
+    pub flavor: Option<String>,
+
+    /// Use a custom palette from a JSON or YAML file
+    #[arg(long, value_name = "FILE", value_parser = palette_file)]
+    pub palette: Option<CustomPalette>,
 
     /// Set color overrides
     #[arg(long, value_parser = json_map::<ColorOverrides>)]
@@ -27,6 +49,16 @@ pub struct Args {
     #[arg(long, value_parser = json_map::<ValueMap>)]
     pub overrides: Option<ValueMap>,
 
+    /// Preview the palette as seen with a color vision deficiency
+    #[arg(long)]
+    pub simulate: Option<Deficiency>,
+
+    /// Load a directory of partials for use with include, import & extends
+    ///
+    /// Partials are named by their path relative to the directory.
+    #[arg(long, value_name = "DIR")]
+    pub partials: Option<PathBuf>,
+
     /// Instead of creating an output, check it against an example
     ///
     /// In single-output mode, a path to the example file must be provided.
@@ -35,10 +67,42 @@ pub struct Args {
     #[arg(long, value_name = "EXAMPLE_PATH")]
     pub check: Option<Option<PathBuf>>,
 
+    /// With --check, write the rendered output to the examples that differ
+    /// instead of failing
+    ///
+    /// Missing examples are created and, with --prune, stale files are
+    /// deleted. Every file that changes is reported.
+    #[arg(long, requires = "check")]
+    pub update: bool,
+
+    /// Write a JSON summary of check mode to FILE, or to stdout if FILE is -
+    #[arg(long, value_name = "FILE", requires = "check")]
+    pub check_summary: Option<PathBuf>,
+
     /// Dry run, don't write anything to disk
     #[arg(long)]
     pub dry_run: bool,
 
+    /// Number of matrix combinations to render in parallel [default: number of CPUs]
+    #[arg(long, short, value_name = "N")]
+    pub jobs: Option<std::num::NonZeroUsize>,
+
+    /// Write a JSON or YAML manifest of the files rendered in multi-output mode
+    #[arg(long, value_name = "FILE")]
+    pub manifest: Option<PathBuf>,
+
+    /// Delete files from a previous render that the matrix no longer produces
+    ///
+    /// Previously generated files are those matching GLOB or, if it is
+    /// omitted, those listed in the existing --manifest. In check mode, stale
+    /// files are reported instead of deleted.
+    #[arg(long, value_name = "GLOB", require_equals = true)]
+    pub prune: Option<Option<String>>,
+
+    /// Watch the template and override files, re-rendering when they change
+    #[arg(long, short, conflicts_with_all = ["check", "batch"])]
+    pub watch: bool,
+
     /// List all Tera filters and functions
     #[arg(short, long)]
     pub list_functions: bool,
@@ -46,6 +110,106 @@ pub struct Args {
     /// Output format of --list-functions
     #[arg(short, long, default_value = "json")]
     pub output_format: OutputFormat,
+
+    /// Use this configuration file instead of looking for one
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Don't look for a whiskers.toml or .whiskersrc configuration file
+    #[arg(long, conflicts_with = "config")]
+    pub no_config: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Report foreground/background color pairs with insufficient contrast
+    Audit(Box<AuditArgs>),
+
+    /// Start a language server for templates on stdin and stdout
+    Lsp,
+
+    /// Check templates for errors without rendering them
+    Validate(ValidateArgs),
+
+    /// Run the tests declared in the frontmatter of templates
+    Test(TestArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct TestArgs {
+    /// Paths of the template files, or - for stdin
+    #[arg(required = true)]
+    pub templates: Vec<FileOrStdin>,
+
+    /// Load a directory of partials for use with include, import & extends
+    #[arg(long, value_name = "DIR")]
+    pub partials: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ValidateArgs {
+    /// Paths of the template files, or - for stdin
+    #[arg(required = true)]
+    pub templates: Vec<FileOrStdin>,
+
+    /// Load a directory of partials for use with include, import & extends
+    #[arg(long, value_name = "DIR")]
+    pub partials: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct AuditArgs {
+    /// Audit a single flavor instead of all of them, which can also be one
+    /// from `--palette`
+    #[arg(long, short, value_parser = FlavorParser)]
+    pub flavor: Option<String>,
+
+    /// Use a custom palette from a JSON or YAML file
+    #[arg(long, value_name = "FILE", value_parser = palette_file)]
+    pub palette: Option<CustomPalette>,
+
+    /// Set color overrides
+    #[arg(long, value_parser = json_map::<ColorOverrides>)]
+    pub color_overrides: Option<ColorOverrides>,
+
+    /// Contrast algorithm to audit with
+    #[arg(long, short, default_value = "wcag")]
+    pub algorithm: Algorithm,
+
+    /// Minimum acceptable contrast
+    ///
+    /// Defaults to 4.5 for WCAG and 60 for APCA. APCA contrast is compared by
+    /// magnitude, regardless of polarity.
+    #[arg(long, short)]
+    pub threshold: Option<f64>,
+
+    /// Foreground colors to check
+    #[arg(long, value_delimiter = ',', default_value = "text,subtext1")]
+    pub foreground: Vec<String>,
+
+    /// Background colors to check the foreground colors against
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "base,mantle,crust,surface0,surface1,surface2"
+    )]
+    pub background: Vec<String>,
+
+    /// Colors that must remain distinguishable from each other
+    #[arg(long, value_delimiter = ',')]
+    pub distinguish: Vec<String>,
+
+    /// Minimum perceptual difference (ΔEOK) between --distinguish colors
+    #[arg(long, default_value = "0.1")]
+    pub min_distance: f64,
+
+    /// Audit the palette as seen with a color vision deficiency
+    #[arg(long, short)]
+    pub simulate: Option<Deficiency>,
+
+    /// Output format of the report
+    #[arg(short, long, default_value = "markdown")]
+    pub output_format: OutputFormat,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -56,6 +220,9 @@ enum Error {
     #[error("Invalid JSON file argument: {message}")]
     InvalidJsonFileArg { message: String },
 
+    #[error("Invalid batch file: {message}")]
+    InvalidBatchFile { message: String },
+
     #[error("Failed to read file: {path}")]
     ReadFile {
         path: String,
@@ -64,6 +231,13 @@ enum Error {
     },
 }
 
+/// Templates to render in a single invocation, listed in a `--batch` file.
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct Batch {
+    /// Paths or globs of the template files, relative to the batch file.
+    pub templates: Vec<String>,
+}
+
 #[derive(Copy, Clone, Debug, clap::ValueEnum)]
 pub enum Flavor {
     Latte,
@@ -83,18 +257,32 @@ impl From<Flavor> for catppuccin::FlavorName {
     }
 }
 
-#[derive(Clone, Debug, serde::Deserialize)]
-pub struct ColorOverrides {
-    #[serde(default)]
-    pub all: HashMap<String, String>,
-    #[serde(default)]
-    pub latte: HashMap<String, String>,
-    #[serde(default)]
-    pub frappe: HashMap<String, String>,
-    #[serde(default)]
-    pub macchiato: HashMap<String, String>,
-    #[serde(default)]
-    pub mocha: HashMap<String, String>,
+/// Accepts any flavor identifier, since custom palettes can name their own,
+/// but lists the built-in [`Flavor`]s as the possible values in `--help`.
+#[derive(Clone)]
+struct FlavorParser;
+
+impl clap::builder::TypedValueParser for FlavorParser {
+    type Value = String;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&clap::Arg>,
+        value: &std::ffi::OsStr,
+    ) -> Result<Self::Value, clap::Error> {
+        clap::builder::StringValueParser::new().parse_ref(cmd, arg, value)
+    }
+
+    fn possible_values(
+        &self,
+    ) -> Option<Box<dyn Iterator<Item = clap::builder::PossibleValue> + '_>> {
+        Some(Box::new(
+            <Flavor as clap::ValueEnum>::value_variants()
+                .iter()
+                .filter_map(clap::ValueEnum::to_possible_value),
+        ))
+    }
 }
 
 #[derive(Clone, Copy, Debug, clap::ValueEnum)]
@@ -105,6 +293,20 @@ pub enum OutputFormat {
     MarkdownTable,
 }
 
+/// Paths of the `--palette` file, and of the `--color-overrides` and
+/// `--overrides` arguments that were given as files rather than JSON literals.
+#[must_use]
+pub fn input_files(matches: &clap::ArgMatches) -> Vec<PathBuf> {
+    ["palette", "color_overrides", "overrides"]
+        .into_iter()
+        .filter_map(|id| matches.get_raw(id))
+        .flatten()
+        .map(Path::new)
+        .filter(|path| path.is_file())
+        .map(Path::to_path_buf)
+        .collect()
+}
+
 fn json_map<T>(s: &str) -> Result<T, Error>
 where
     T: serde::de::DeserializeOwned,
@@ -123,3 +325,30 @@ where
         })
     }
 }
+
+fn palette_file(s: &str) -> Result<CustomPalette, crate::models::Error> {
+    CustomPalette::from_file(Path::new(s))
+}
+
+fn batch_file(s: &str) -> Result<Batch, Error> {
+    let path = Path::new(s);
+    let contents = std::fs::read_to_string(path).map_err(|e| Error::ReadFile {
+        path: s.to_string(),
+        source: e,
+    })?;
+    let is_yaml = path
+        .extension()
+        .is_some_and(|ext| ext == "yaml" || ext == "yml");
+    let mut batch: Batch = if is_yaml {
+        serde_yaml::from_str(&contents).map_err(|e| e.to_string())
+    } else {
+        serde_json::from_str(&contents).map_err(|e| e.to_string())
+    }
+    .map_err(|message| Error::InvalidBatchFile { message })?;
+
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    for template in &mut batch.templates {
+        *template = dir.join(&*template).display().to_string();
+    }
+    Ok(batch)
+}
@@ -0,0 +1,258 @@
+//! conversions between sRGB and the OKLab/OKLCH perceptual color spaces.
+//!
+//! see <https://bottosson.github.io/posts/oklab/> for the reference implementation.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Lab {
+    pub l: f64,
+    pub a: f64,
+    pub b: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Lch {
+    pub l: f64,
+    pub c: f64,
+    /// hue in degrees, in the range `0.0..360.0`.
+    pub h: f64,
+}
+
+const LINEAR_TO_LMS: [[f64; 3]; 3] = [
+    [0.412_221_470_8, 0.536_332_536_3, 0.051_457_565_3],
+    [0.211_903_498_2, 0.680_699_545_1, 0.107_406_579_3],
+    [0.088_302_461_9, 0.281_718_837_6, 0.629_978_700_5],
+];
+
+const LMS_TO_LAB: [[f64; 3]; 3] = [
+    [0.210_454_255_3, 0.793_617_785, -0.004_072_046_8],
+    [1.977_998_495_1, -2.428_592_205, 0.450_593_709_9],
+    [0.025_904_037_1, 0.782_771_766_2, -0.808_675_766],
+];
+
+const LAB_TO_LMS: [[f64; 3]; 3] = [
+    [1.0, 0.396_337_777_4, 0.215_803_757_3],
+    [1.0, -0.105_561_345_8, -0.063_854_172_8],
+    [1.0, -0.089_484_177_5, -1.291_485_548],
+];
+
+const LMS_TO_LINEAR: [[f64; 3]; 3] = [
+    [4.076_741_662_1, -3.307_711_591_3, 0.230_969_929_2],
+    [-1.268_438_004_6, 2.609_757_401_1, -0.341_319_396_5],
+    [-0.004_196_086_3, -0.703_418_614_7, 1.707_614_701],
+];
+
+/// below this chroma, a color is considered achromatic and its hue meaningless.
+const ACHROMATIC_THRESHOLD: f64 = 1e-4;
+
+/// convert an 8-bit sRGB channel to linear light in the range `0.0..=1.0`.
+#[must_use]
+pub fn srgb_to_linear(c: u8) -> f64 {
+    let c = f64::from(c) / 255.0;
+    if c <= 0.040_45 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// convert linear light back to an 8-bit sRGB channel, clamping out-of-range values.
+#[must_use]
+pub fn linear_to_srgb(c: f64) -> u8 {
+    let c = if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055f64.mul_add(c.powf(1.0 / 2.4), -0.055)
+    };
+    (c.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+impl Lab {
+    #[must_use]
+    pub fn from_rgb(r: u8, g: u8, b: u8) -> Self {
+        Self::from_linear([srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b)])
+    }
+
+    #[must_use]
+    pub fn from_linear(rgb: [f64; 3]) -> Self {
+        let lms = mul(&LINEAR_TO_LMS, rgb).map(f64::cbrt);
+        let [l, a, b] = mul(&LMS_TO_LAB, lms);
+        Self { l, a, b }
+    }
+
+    #[must_use]
+    pub fn to_linear(self) -> [f64; 3] {
+        let lms = mul(&LAB_TO_LMS, [self.l, self.a, self.b]).map(|c| c.powi(3));
+        mul(&LMS_TO_LINEAR, lms)
+    }
+
+    #[must_use]
+    pub fn to_lch(self) -> Lch {
+        let c = self.a.hypot(self.b);
+        let h = if c < ACHROMATIC_THRESHOLD {
+            0.0
+        } else {
+            self.b.atan2(self.a).to_degrees().rem_euclid(360.0)
+        };
+        Lch { l: self.l, c, h }
+    }
+
+    /// linearly interpolate towards `other`, where `amount` is the weight of `self`.
+    #[must_use]
+    pub fn mix(self, other: Self, amount: f64) -> Self {
+        Self {
+            l: lerp(other.l, self.l, amount),
+            a: lerp(other.a, self.a, amount),
+            b: lerp(other.b, self.b, amount),
+        }
+    }
+}
+
+impl Lch {
+    #[must_use]
+    pub fn from_rgb(r: u8, g: u8, b: u8) -> Self {
+        Lab::from_rgb(r, g, b).to_lch()
+    }
+
+    #[must_use]
+    pub fn to_lab(self) -> Lab {
+        let h = self.h.to_radians();
+        Lab {
+            l: self.l,
+            a: self.c * h.cos(),
+            b: self.c * h.sin(),
+        }
+    }
+
+    /// convert to 8-bit sRGB.
+    ///
+    /// colors outside of the sRGB gamut have their chroma reduced until they
+    /// fit, which preserves lightness and hue far better than clamping.
+    #[must_use]
+    pub fn to_rgb(self) -> [u8; 3] {
+        let lch = Self {
+            l: self.l.clamp(0.0, 1.0),
+            c: self.c.max(0.0),
+            ..self
+        };
+
+        let mut linear = lch.to_lab().to_linear();
+        if !in_gamut(linear) {
+            let (mut lo, mut hi) = (0.0, lch.c);
+            for _ in 0..24 {
+                let c = f64::midpoint(lo, hi);
+                if in_gamut(Self { c, ..lch }.to_lab().to_linear()) {
+                    lo = c;
+                } else {
+                    hi = c;
+                }
+            }
+            linear = Self { c: lo, ..lch }.to_lab().to_linear();
+        }
+
+        linear.map(linear_to_srgb)
+    }
+
+    /// interpolate towards `other` along the shortest hue arc, where `amount`
+    /// is the weight of `self`.
+    #[must_use]
+    pub fn mix(self, other: Self, amount: f64) -> Self {
+        // an achromatic color has no meaningful hue, so take the other one's.
+        let (h1, h2) = match (
+            self.c < ACHROMATIC_THRESHOLD,
+            other.c < ACHROMATIC_THRESHOLD,
+        ) {
+            (true, false) => (other.h, other.h),
+            (false, true) => (self.h, self.h),
+            _ => (self.h, other.h),
+        };
+        let mut delta = h1 - h2;
+        if delta > 180.0 {
+            delta -= 360.0;
+        } else if delta < -180.0 {
+            delta += 360.0;
+        }
+        Self {
+            l: lerp(other.l, self.l, amount),
+            c: lerp(other.c, self.c, amount),
+            h: delta.mul_add(amount, h2).rem_euclid(360.0),
+        }
+    }
+}
+
+fn in_gamut(linear: [f64; 3]) -> bool {
+    const EPSILON: f64 = 1e-6;
+    linear
+        .iter()
+        .all(|c| (-EPSILON..=1.0 + EPSILON).contains(c))
+}
+
+fn mul(matrix: &[[f64; 3]; 3], v: [f64; 3]) -> [f64; 3] {
+    matrix.map(|row| row[2].mul_add(v[2], row[1].mul_add(v[1], row[0] * v[0])))
+}
+
+fn lerp(from: f64, to: f64, amount: f64) -> f64 {
+    (to - from).mul_add(amount, from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn oklab_of_pure_red() {
+        let lab = Lab::from_rgb(255, 0, 0);
+        assert_close(lab.l, 0.627_96);
+        assert_close(lab.a, 0.224_86);
+        assert_close(lab.b, 0.125_85);
+    }
+
+    #[test]
+    fn white_is_achromatic() {
+        let lch = Lch::from_rgb(255, 255, 255);
+        assert_close(lch.l, 1.0);
+        assert_close(lch.c, 0.0);
+        assert_close(lch.h, 0.0);
+    }
+
+    #[test]
+    fn rgb_round_trip() {
+        for rgb in [[210, 15, 57], [30, 30, 46], [166, 227, 161], [0, 0, 0]] {
+            assert_eq!(Lch::from_rgb(rgb[0], rgb[1], rgb[2]).to_rgb(), rgb);
+        }
+    }
+
+    #[test]
+    fn out_of_gamut_chroma_is_reduced() {
+        let lch = Lch {
+            l: 0.9,
+            c: 0.4,
+            h: 30.0,
+        };
+        let [r, g, b] = lch.to_rgb();
+        let mapped = Lch::from_rgb(r, g, b);
+        assert_close(mapped.l, 0.9);
+        assert!(mapped.c < 0.4);
+    }
+
+    #[test]
+    fn mix_takes_shortest_hue_arc() {
+        let a = Lch {
+            l: 0.5,
+            c: 0.1,
+            h: 350.0,
+        };
+        let b = Lch {
+            l: 0.5,
+            c: 0.1,
+            h: 10.0,
+        };
+        assert_close(a.mix(b, 0.5).h, 0.0);
+    }
+}
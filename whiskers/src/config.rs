@@ -0,0 +1,265 @@
+//! project configuration from a `whiskers.toml` or `.whiskersrc` file, which
+//! sets defaults for the command-line arguments.
+
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cli::{Args, AuditArgs},
+    context::merge_values,
+    models::{self, ColorOverrides, CustomPalette},
+    render,
+    simulation::Deficiency,
+};
+
+/// Names of the configuration file, in order of preference. Both are TOML.
+pub const FILENAMES: [&str; 2] = ["whiskers.toml", ".whiskersrc"];
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub flavor: Option<String>,
+    /// Path to a custom palette file, relative to the configuration file.
+    pub palette: Option<PathBuf>,
+    pub color_overrides: Option<tera::Value>,
+    pub overrides: Option<HashMap<String, tera::Value>>,
+    pub simulate: Option<Deficiency>,
+    /// Path to a partials directory, relative to the configuration file.
+    pub partials: Option<PathBuf>,
+    pub jobs: Option<NonZeroUsize>,
+    /// Default `hex_prefix` for templates that don't set it.
+    pub hex_prefix: Option<String>,
+    /// Default `capitalize_hex` for templates that don't set it.
+    pub capitalize_hex: Option<bool>,
+    /// Paths or globs of the templates to render when none are given on the
+    /// command line, relative to the configuration file.
+    pub templates: Vec<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to read config file {path}")]
+    Read {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Failed to parse config file {path}")]
+    Parse {
+        path: String,
+        #[source]
+        source: toml::de::Error,
+    },
+
+    #[error("Invalid {key} in config file: {message}")]
+    InvalidValue { key: &'static str, message: String },
+
+    #[error("Failed to load palette from config file")]
+    Palette(#[from] models::Error),
+}
+
+/// Find the configuration file in `dir` or the closest of its ancestors.
+///
+/// The returned path is relative to `dir`, so that paths in the configuration
+/// resolve to short paths when `dir` is the working directory.
+#[must_use]
+pub fn discover(dir: &Path) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    for ancestor in dir.ancestors() {
+        for filename in FILENAMES {
+            if ancestor.join(filename).is_file() {
+                return Some(relative.join(filename));
+            }
+        }
+        relative.push("..");
+    }
+    None
+}
+
+impl Config {
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let contents = std::fs::read_to_string(path).map_err(|e| Error::Read {
+            path: path.display().to_string(),
+            source: e,
+        })?;
+        let mut config: Self = toml::from_str(&contents).map_err(|e| Error::Parse {
+            path: path.display().to_string(),
+            source: e,
+        })?;
+
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        config.palette = config.palette.map(|palette| dir.join(palette));
+        config.partials = config.partials.map(|partials| dir.join(partials));
+        for template in &mut config.templates {
+            *template = dir.join(&*template).display().to_string();
+        }
+        Ok(config)
+    }
+
+    /// Use the configuration for the arguments that weren't given on the
+    /// command line. Overrides from both are merged, with the command line
+    /// taking precedence.
+    ///
+    /// Returns the render options that have no command-line argument, for the
+    /// render options built from `args` to fall back on.
+    pub fn apply(self, args: &mut Args) -> Result<render::Options, Error> {
+        if args.template.is_empty() && args.batch.is_none() {
+            for template in self.templates {
+                args.template
+                    .push(template.parse().map_err(|e: clap_stdin::StdinError| {
+                        Error::InvalidValue {
+                            key: "templates",
+                            message: e.to_string(),
+                        }
+                    })?);
+            }
+        }
+
+        args.flavor = args.flavor.take().or(self.flavor);
+        if args.palette.is_none() {
+            args.palette = load_palette(self.palette.as_deref())?;
+        }
+        args.simulate = args.simulate.or(self.simulate);
+        args.partials = args.partials.take().or(self.partials);
+        args.jobs = args.jobs.or(self.jobs);
+
+        if let Some(overrides) = self.overrides {
+            let mut merged = overrides;
+            for (key, value) in args.overrides.take().unwrap_or_default() {
+                let value = match merged.get(&key) {
+                    Some(base) => merge_values(base, &value),
+                    None => value,
+                };
+                merged.insert(key, value);
+            }
+            args.overrides = Some(merged);
+        }
+
+        if let Some(color_overrides) = self.color_overrides {
+            args.color_overrides = Some(merge_color_overrides(
+                color_overrides,
+                args.color_overrides.as_ref(),
+            )?);
+        }
+
+        Ok(render::Options {
+            hex_prefix: self.hex_prefix,
+            capitalize_hex: self.capitalize_hex,
+            ..render::Options::default()
+        })
+    }
+
+    /// Use the configuration for the `audit` arguments that weren't given on
+    /// the command line, which are the flavor and the palette.
+    pub fn apply_audit(self, args: &mut AuditArgs) -> Result<(), Error> {
+        args.flavor = args.flavor.take().or(self.flavor);
+        if args.palette.is_none() {
+            args.palette = load_palette(self.palette.as_deref())?;
+        }
+        if let Some(color_overrides) = self.color_overrides {
+            args.color_overrides = Some(merge_color_overrides(
+                color_overrides,
+                args.color_overrides.as_ref(),
+            )?);
+        }
+        Ok(())
+    }
+
+    /// The render options for the `test` and `validate` subcommands, which
+    /// have no arguments for them other than `--partials`.
+    pub fn render_options(self) -> Result<render::Options, Error> {
+        Ok(render::Options {
+            flavor: self.flavor,
+            palette: load_palette(self.palette.as_deref())?,
+            color_overrides: self
+                .color_overrides
+                .map(|color_overrides| merge_color_overrides(color_overrides, None))
+                .transpose()?,
+            overrides: self.overrides,
+            simulate: self.simulate,
+            partials: self.partials,
+            jobs: self.jobs,
+            hex_prefix: self.hex_prefix,
+            capitalize_hex: self.capitalize_hex,
+        })
+    }
+}
+
+fn load_palette(path: Option<&Path>) -> Result<Option<CustomPalette>, Error> {
+    Ok(path.map(CustomPalette::from_file).transpose()?)
+}
+
+/// Merge the color overrides from the command line, if any, into those from
+/// the configuration.
+fn merge_color_overrides(
+    config: tera::Value,
+    cli: Option<&ColorOverrides>,
+) -> Result<ColorOverrides, Error> {
+    let merged = match cli {
+        Some(cli) => {
+            let cli = tera::to_value(cli).map_err(|e| invalid_color_overrides(&e))?;
+            merge_values(&config, &cli)
+        }
+        None => config,
+    };
+    tera::from_value(merged).map_err(|e| invalid_color_overrides(&e))
+}
+
+fn invalid_color_overrides(e: &serde_json::Error) -> Error {
+    Error::InvalidValue {
+        key: "color_overrides",
+        message: e.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser as _;
+
+    use super::*;
+
+    #[test]
+    fn command_line_takes_precedence() {
+        let config: Config = toml::from_str(
+            r#"
+            flavor = "latte"
+            jobs = 2
+            hex_prefix = "0x"
+            overrides = { accent = "mauve", opts = { a = 1, b = 2 } }
+            color_overrides = { all = { red = "ff0000" }, mocha = { blue = "0000ff" } }
+            "#,
+        )
+        .expect("config is valid");
+        let mut args = Args::parse_from([
+            "whiskers",
+            "template.tera",
+            "--flavor",
+            "mocha",
+            "--overrides",
+            r#"{"opts": {"b": 3}}"#,
+            "--color-overrides",
+            r#"{"all": {"green": "00ff00"}}"#,
+        ]);
+        let defaults = config.apply(&mut args).expect("config applies");
+
+        assert_eq!(args.flavor.as_deref(), Some("mocha"));
+        assert_eq!(args.jobs.map(NonZeroUsize::get), Some(2));
+        assert_eq!(defaults.hex_prefix.as_deref(), Some("0x"));
+        let overrides = args.overrides.expect("overrides are merged");
+        assert_eq!(overrides["accent"], "mauve");
+        assert_eq!(overrides["opts"], serde_json::json!({ "a": 1, "b": 3 }));
+        let color_overrides = args.color_overrides.expect("color overrides are merged");
+        assert_eq!(color_overrides.all["red"], "ff0000");
+        assert_eq!(color_overrides.all["green"], "00ff00");
+        assert_eq!(color_overrides.mocha["blue"], "0000ff");
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(toml::from_str::<Config>("flavour = \"mocha\"").is_err());
+    }
+}
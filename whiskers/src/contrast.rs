@@ -0,0 +1,100 @@
+//! contrast metrics for judging the readability of one color on another.
+
+use crate::colorspace::srgb_to_linear;
+
+/// the relative luminance of an sRGB color, as defined by WCAG 2.
+#[must_use]
+pub fn relative_luminance([r, g, b]: [u8; 3]) -> f64 {
+    0.0722f64.mul_add(
+        srgb_to_linear(b),
+        0.2126f64.mul_add(srgb_to_linear(r), 0.7152 * srgb_to_linear(g)),
+    )
+}
+
+/// the WCAG 2 contrast ratio between two colors, from `1.0` to `21.0`.
+///
+/// the ratio is symmetric, so the order of the arguments doesn't matter.
+#[must_use]
+pub fn wcag(a: [u8; 3], b: [u8; 3]) -> f64 {
+    let (a, b) = (relative_luminance(a), relative_luminance(b));
+    let (lighter, darker) = if a > b { (a, b) } else { (b, a) };
+    (lighter + 0.05) / (darker + 0.05)
+}
+
+/// the APCA lightness contrast (Lc) of `fg` text on a `bg` background.
+///
+/// the result is roughly in the range `-108.0..=106.0`; it is positive for
+/// dark text on a light background and negative for light text on a dark
+/// background. uses the APCA-W3 0.0.98G-4g constants.
+#[must_use]
+pub fn apca(fg: [u8; 3], bg: [u8; 3]) -> f64 {
+    const NORM_BG: f64 = 0.56;
+    const NORM_TXT: f64 = 0.57;
+    const REV_TXT: f64 = 0.62;
+    const REV_BG: f64 = 0.65;
+    const SCALE: f64 = 1.14;
+    const OFFSET: f64 = 0.027;
+    const LOW_CLIP: f64 = 0.1;
+    const DELTA_Y_MIN: f64 = 0.0005;
+
+    let (text, background) = (apca_luminance(fg), apca_luminance(bg));
+    if (background - text).abs() < DELTA_Y_MIN {
+        return 0.0;
+    }
+
+    let contrast = if background > text {
+        let sapc = (background.powf(NORM_BG) - text.powf(NORM_TXT)) * SCALE;
+        if sapc < LOW_CLIP {
+            0.0
+        } else {
+            sapc - OFFSET
+        }
+    } else {
+        let sapc = (background.powf(REV_BG) - text.powf(REV_TXT)) * SCALE;
+        if sapc > -LOW_CLIP {
+            0.0
+        } else {
+            sapc + OFFSET
+        }
+    };
+    contrast * 100.0
+}
+
+/// screen luminance as estimated by APCA, with a soft clamp for near-black colors.
+fn apca_luminance([r, g, b]: [u8; 3]) -> f64 {
+    const BLACK_THRESHOLD: f64 = 0.022;
+    const BLACK_CLAMP: f64 = 1.414;
+
+    let channel = |c: u8| (f64::from(c) / 255.0).powf(2.4);
+    let y = 0.072_175f64.mul_add(
+        channel(b),
+        0.212_672_9f64.mul_add(channel(r), 0.715_152_2 * channel(g)),
+    );
+    if y < BLACK_THRESHOLD {
+        y + (BLACK_THRESHOLD - y).powf(BLACK_CLAMP)
+    } else {
+        y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: [u8; 3] = [0, 0, 0];
+    const WHITE: [u8; 3] = [255, 255, 255];
+
+    #[test]
+    fn wcag_extremes() {
+        assert!((wcag(BLACK, WHITE) - 21.0).abs() < 1e-9);
+        assert!((wcag(WHITE, BLACK) - 21.0).abs() < 1e-9);
+        assert!((wcag(WHITE, WHITE) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn apca_reference_values() {
+        assert!((apca(BLACK, WHITE) - 106.04).abs() < 0.01);
+        assert!((apca(WHITE, BLACK) + 107.88).abs() < 0.01);
+        assert!((apca([0x88, 0x88, 0x88], [0x88, 0x88, 0x88])).abs() < 1e-9);
+    }
+}
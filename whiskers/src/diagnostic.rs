@@ -0,0 +1,428 @@
+//! rustc-style reports of errors in templates, which show where in the
+//! template the error happened.
+//!
+//! Tera only knows about the body of a template, and doesn't locate errors
+//! that happen while rendering, so they are located here: syntax errors are
+//! moved past the frontmatter, and render errors point at the first use of
+//! the variable, filter or function that failed.
+
+use std::{fmt, ops::Range};
+
+use crate::{frontmatter, render};
+
+/// An error in a template, with where it happened.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The error and its causes.
+    pub message: String,
+    /// The name of the template, such as its path.
+    pub name: String,
+    pub span: Option<Span>,
+    /// The matrix combination that was being rendered, as `key=value` pairs.
+    pub combination: Option<String>,
+}
+
+/// Where in a template an error happened.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Span {
+    /// The line, counting from one.
+    pub line: usize,
+    /// The column in characters, counting from one.
+    pub column: usize,
+    /// The number of characters to highlight.
+    pub length: usize,
+    /// The text of the line.
+    pub text: String,
+}
+
+impl Diagnostic {
+    /// Locate `err`, which happened while rendering `source`, within it.
+    #[must_use]
+    pub fn new(err: &render::Error, source: &str, name: &str) -> Self {
+        let (err, combination) = match err {
+            render::Error::Combination {
+                combination,
+                source,
+            } => (source.as_ref(), Some(combination.clone())),
+            err => (err, None),
+        };
+
+        let locator = Locator::new(source);
+        let span = match err {
+            render::Error::Frontmatter(frontmatter::Error::InvalidYaml {
+                line, column, ..
+            }) => locator.span(locator.frontmatter_line + line, *column, 1),
+            render::Error::InvalidOptions(_) => {
+                let (line, column) = locator.section();
+                locator.span(line, column, "whiskers".len())
+            }
+            render::Error::Matrix(_) | render::Error::MissingFilename => locator.key("matrix"),
+            render::Error::IncompatibleVersion { .. } => locator.key("version"),
+            render::Error::FrontmatterPartials(_) | render::Error::PartialsUnsupported => {
+                locator.key("partials")
+            }
+            render::Error::InvalidTemplate(e) => error_location(e)
+                .and_then(|(line, column)| locator.span(locator.body_line + line, column, 1)),
+            render::Error::Render(e) => locator.find_use(e, locator.body_start..source.len()),
+            render::Error::Filename(e) => {
+                let (line, _) = locator.key_position("filename");
+                locator
+                    .line_range(line)
+                    .and_then(|range| locator.find_use(e, range))
+                    .or_else(|| locator.key("filename"))
+            }
+            _ => None,
+        };
+
+        Self {
+            message: describe(err),
+            name: name.to_string(),
+            span,
+            combination,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.message)?;
+        let width = self
+            .span
+            .as_ref()
+            .map_or(1, |span| span.line.to_string().len());
+        let gutter = " ".repeat(width);
+
+        match self.span {
+            Some(ref span) => {
+                writeln!(f, "{gutter}--> {}:{}:{}", self.name, span.line, span.column)?;
+                writeln!(f, "{gutter} |")?;
+                writeln!(f, "{} | {}", span.line, span.text)?;
+                // keep tabs so that the highlight lines up with the text
+                let indent = span
+                    .text
+                    .chars()
+                    .take(span.column - 1)
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect::<String>();
+                write!(f, "{gutter} | {indent}{}", "^".repeat(span.length.max(1)))?;
+            }
+            None => write!(f, "{gutter}--> {}", self.name)?,
+        }
+
+        if let Some(ref combination) = self.combination {
+            write!(
+                f,
+                "\n{gutter} |\n{gutter} = note: while rendering matrix combination {combination}"
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// Finds lines in a template, so that errors in the frontmatter and body can
+/// be located within the whole template.
+pub(crate) struct Locator<'a> {
+    source: &'a str,
+    /// The line that the frontmatter starts on, counting from zero.
+    pub frontmatter_line: usize,
+    /// The line that the body starts on, counting from zero.
+    pub body_line: usize,
+    /// The byte offset of the body.
+    body_start: usize,
+}
+
+impl<'a> Locator<'a> {
+    pub fn new(source: &'a str) -> Self {
+        let (frontmatter_line, body_line) = frontmatter::line_offsets(source);
+        let body_start = frontmatter::split(source).map_or(0, |(_, body)| {
+            body.as_ptr() as usize - source.as_ptr() as usize
+        });
+        Self {
+            source,
+            frontmatter_line,
+            body_line,
+            body_start,
+        }
+    }
+
+    /// The line and column, counting from one, of the `whiskers` section, or
+    /// of the frontmatter if there is no such section.
+    pub fn section(&self) -> (usize, usize) {
+        self.frontmatter_lines()
+            .find(|(_, line)| line.starts_with("whiskers:"))
+            .map_or((self.frontmatter_line + 1, 1), |(i, _)| (i + 1, 1))
+    }
+
+    /// The line and column, counting from one, of `key` in the `whiskers`
+    /// section, or of the section itself if the key can't be found, such as
+    /// when it is written in flow style.
+    pub fn key_position(&self, key: &str) -> (usize, usize) {
+        let mut in_section = false;
+        for (i, line) in self.frontmatter_lines() {
+            let indent = line.len() - line.trim_start().len();
+            if indent == 0 {
+                if in_section {
+                    break;
+                }
+                in_section = line.starts_with("whiskers:");
+            } else if in_section && line.trim_start().starts_with(&format!("{key}:")) {
+                return (i + 1, indent + 1);
+            }
+        }
+        self.section()
+    }
+
+    /// A span highlighting `key` in the `whiskers` section.
+    fn key(&self, key: &str) -> Option<Span> {
+        let (line, column) = self.key_position(key);
+        self.span(line, column, key.len())
+    }
+
+    fn frontmatter_lines(&self) -> impl Iterator<Item = (usize, &'a str)> {
+        self.source
+            .lines()
+            .enumerate()
+            .take(self.body_line)
+            .skip(self.frontmatter_line)
+    }
+
+    fn span(&self, line: usize, column: usize, length: usize) -> Option<Span> {
+        let text = self.source.lines().nth(line.checked_sub(1)?)?;
+        Some(Span {
+            line,
+            column,
+            length,
+            text: text.trim_end_matches('\r').to_string(),
+        })
+    }
+
+    /// The byte range of a line, counting from one.
+    fn line_range(&self, line: usize) -> Option<Range<usize>> {
+        let text = self.source.lines().nth(line.checked_sub(1)?)?;
+        let start = text.as_ptr() as usize - self.source.as_ptr() as usize;
+        Some(start..start + text.len())
+    }
+
+    /// A span highlighting the first use, within the tags in `range`, of
+    /// what caused a render error.
+    fn find_use(&self, err: &tera::Error, range: Range<usize>) -> Option<Span> {
+        let culprit = Culprit::of(err)?;
+        let text = &self.source[range.clone()];
+        let offset =
+            tags(text).find_map(|tag| culprit.find(&text[tag.clone()]).map(|i| tag.start + i))?;
+
+        let offset = range.start + offset;
+        let line_start = self.source[..offset].rfind('\n').map_or(0, |i| i + 1);
+        self.span(
+            self.source[..offset].matches('\n').count() + 1,
+            self.source[line_start..offset].chars().count() + 1,
+            culprit.name().chars().count(),
+        )
+    }
+}
+
+/// What caused a render error.
+enum Culprit<'a> {
+    Variable(&'a str),
+    Filter(&'a str),
+    Function(&'a str),
+    Test(&'a str),
+}
+
+impl<'a> Culprit<'a> {
+    fn of(err: &'a tera::Error) -> Option<Self> {
+        let mut source: Option<&(dyn std::error::Error + 'static)> = Some(err);
+        while let Some(err) = source {
+            if let Some(err) = err.downcast_ref::<tera::Error>() {
+                match err.kind {
+                    tera::ErrorKind::FilterNotFound(ref name)
+                    | tera::ErrorKind::CallFilter(ref name) => return Some(Self::Filter(name)),
+                    tera::ErrorKind::FunctionNotFound(ref name)
+                    | tera::ErrorKind::CallFunction(ref name) => return Some(Self::Function(name)),
+                    tera::ErrorKind::TestNotFound(ref name)
+                    | tera::ErrorKind::CallTest(ref name) => return Some(Self::Test(name)),
+                    tera::ErrorKind::Msg(ref message) => {
+                        // errors in partials can't be located in this template
+                        if message.contains("(error happened in ") {
+                            return None;
+                        }
+                        if let Some(rest) = message.strip_prefix("Variable `") {
+                            return rest.split_once('`').map(|(name, _)| Self::Variable(name));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            source = err.source();
+        }
+        None
+    }
+
+    const fn name(&self) -> &'a str {
+        match *self {
+            Self::Variable(name) | Self::Filter(name) | Self::Function(name) | Self::Test(name) => {
+                name
+            }
+        }
+    }
+
+    /// The byte offset of the first use in `tag`.
+    fn find(&self, tag: &str) -> Option<usize> {
+        let name = self.name();
+        let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+        tag.match_indices(name).map(|(i, _)| i).find(|&i| {
+            let before = tag[..i].trim_end();
+            let after = tag[i + name.len()..].trim_start();
+            if tag[..i].ends_with(|c: char| is_ident(c) || c == '.')
+                || tag[i + name.len()..].starts_with(is_ident)
+            {
+                return false;
+            }
+            match *self {
+                Self::Variable(_) => !after.starts_with('('),
+                Self::Filter(_) => before.ends_with('|'),
+                Self::Function(_) => after.starts_with('('),
+                Self::Test(_) => before.ends_with(" is") || before.ends_with(" not"),
+            }
+        })
+    }
+}
+
+/// The byte ranges of the `{{ }}` and `{% %}` tags in `text`.
+fn tags(text: &str) -> impl Iterator<Item = Range<usize>> + '_ {
+    let mut position = 0;
+    std::iter::from_fn(move || loop {
+        let start = position + text[position..].find('{')?;
+        let close = match text[start + 1..].chars().next() {
+            Some('{') => "}}",
+            Some('%') => "%}",
+            Some('#') => "#}",
+            _ => {
+                position = start + 1;
+                continue;
+            }
+        };
+        let end = text[start + 2..]
+            .find(close)
+            .map_or(text.len(), |i| start + 2 + i + close.len());
+        position = end;
+        if close != "#}" {
+            return Some(start..end);
+        }
+    })
+}
+
+/// The line and column, counting from one, of a Tera syntax error.
+pub(crate) fn error_location(err: &dyn std::error::Error) -> Option<(usize, usize)> {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        let message = err.to_string();
+        if let Some((_, location)) = message.split_once(" --> ") {
+            let location = location.lines().next().unwrap_or_default();
+            let (line, column) = location.trim().split_once(':')?;
+            return Some((line.parse().ok()?, column.parse().ok()?));
+        }
+        source = err.source();
+    }
+    None
+}
+
+/// The error and its causes on a single line. Tera syntax errors are reduced
+/// to what was expected and Tera's "Failed to render" is left out, as the
+/// location is reported separately.
+pub(crate) fn describe(err: &dyn std::error::Error) -> String {
+    let mut messages = vec![];
+    let mut source = Some(err);
+    while let Some(err) = source {
+        let message = err.to_string();
+        source = err.source();
+        if message.starts_with("Failed to render '") && !message.contains("(error happened in ") {
+            continue;
+        }
+        let message = if message.contains(" --> ") {
+            message
+                .lines()
+                .find_map(|line| line.trim().strip_prefix("= "))
+                .unwrap_or_else(|| message.lines().next().unwrap_or_default())
+                .to_string()
+        } else {
+            message
+        };
+        messages.push(message);
+    }
+    messages.join(": ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(source: &str) -> Diagnostic {
+        let mut renderer = render::Renderer::new(render::Options {
+            flavor: Some("mocha".to_string()),
+            ..render::Options::default()
+        });
+        let err = renderer
+            .render(&render::RenderRequest::new(source))
+            .expect_err("template fails to render");
+        Diagnostic::new(&err, source, "theme.tera")
+    }
+
+    #[test]
+    fn locates_syntax_errors_after_frontmatter() {
+        let source = "---\nwhiskers:\n  version: \"2\"\n---\nline\n{{ red. }}\n";
+        let diagnostic = diagnostic(source);
+        assert!(diagnostic
+            .message
+            .starts_with("Template is invalid: Failed to parse 'template': expected"));
+        assert!(diagnostic
+            .to_string()
+            .ends_with("\n --> theme.tera:6:7\n  |\n6 | {{ red. }}\n  |       ^"));
+    }
+
+    #[test]
+    fn locates_render_errors() {
+        let source = "---\nwhiskers:\n  version: \"2\"\n---\n{{ flavor.name }}\n\t{{ flavor.name | nope }}\n";
+        let span = diagnostic(source).span.expect("error is located");
+        assert_eq!((span.line, span.column, span.length), (6, 19, 4));
+
+        let source = "{% if true %}\n{{ flavor.name }} {{ flavor.nonexistent }}\n{% endif %}";
+        assert_eq!(
+            diagnostic(source).to_string(),
+            "Template render failed: Variable `flavor.nonexistent` not found in context while \
+             rendering 'template'\n \
+             --> theme.tera:2:22\n  |\n\
+             2 | {{ flavor.name }} {{ flavor.nonexistent }}\n  \
+             |                      ^^^^^^^^^^^^^^^^^^"
+        );
+    }
+
+    #[test]
+    fn notes_matrix_combination() {
+        let source = "---\nwhiskers:\n  version: \"2\"\n  matrix:\n    - flavor\n  filename: \"{{ flavor.nam }}.txt\"\n---\n{{ red.hex }}\n";
+        let diagnostic = diagnostic(source);
+        assert_eq!(diagnostic.combination.as_deref(), Some("flavor=mocha"));
+        let span = diagnostic.span.as_ref().expect("error is located");
+        assert_eq!((span.line, span.column), (6, 17));
+        assert!(diagnostic
+            .to_string()
+            .ends_with("\n  |\n  = note: while rendering matrix combination flavor=mocha"));
+    }
+
+    #[test]
+    fn unlocated_errors() {
+        let diagnostic = Diagnostic::new(
+            &render::Error::UnknownFlavor {
+                flavor: "midnight".to_string(),
+            },
+            "{{ red.hex }}",
+            "theme.tera",
+        );
+        assert_eq!(
+            diagnostic.to_string(),
+            "Palette has no midnight flavor\n --> theme.tera"
+        );
+    }
+}
@@ -0,0 +1,139 @@
+//! encoding of rendered output, for ports that need something other than
+//! UTF-8 with whatever line endings the template happens to use.
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+pub enum Encoding {
+    #[default]
+    #[serde(rename = "utf-8")]
+    Utf8,
+    #[serde(rename = "utf-16le")]
+    Utf16Le,
+    #[serde(rename = "utf-16be")]
+    Utf16Be,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+/// How rendered output is written, set by the `encoding`, `bom` and
+/// `line_endings` frontmatter options.
+#[derive(Clone, Copy, Debug, Default, serde::Deserialize)]
+pub struct Output {
+    #[serde(default)]
+    pub encoding: Encoding,
+    /// Start the output with a byte order mark.
+    #[serde(default)]
+    pub bom: bool,
+    /// Normalize line endings. They are left as they are if unset.
+    pub line_endings: Option<LineEnding>,
+}
+
+impl Output {
+    /// Encode rendered text into the bytes to be written.
+    #[must_use]
+    pub fn encode(&self, text: &str) -> Vec<u8> {
+        let text = match self.line_endings {
+            Some(LineEnding::Lf) => text.replace("\r\n", "\n"),
+            Some(LineEnding::Crlf) => text.replace("\r\n", "\n").replace('\n', "\r\n"),
+            None => text.to_string(),
+        };
+
+        let mut bytes = vec![];
+        match self.encoding {
+            Encoding::Utf8 => {
+                if self.bom {
+                    bytes.extend_from_slice(b"\xEF\xBB\xBF");
+                }
+                bytes.extend_from_slice(text.as_bytes());
+            }
+            Encoding::Utf16Le => {
+                if self.bom {
+                    bytes.extend_from_slice(b"\xFF\xFE");
+                }
+                bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+            }
+            Encoding::Utf16Be => {
+                if self.bom {
+                    bytes.extend_from_slice(b"\xFE\xFF");
+                }
+                bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+            }
+        }
+        bytes
+    }
+
+    /// Decode encoded output back into text, without its byte order mark.
+    #[must_use]
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        match self.encoding {
+            Encoding::Utf8 => {
+                let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+                String::from_utf8_lossy(bytes).into_owned()
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let units = bytes.chunks_exact(2).map(|pair| {
+                    let pair = [pair[0], pair[1]];
+                    if self.encoding == Encoding::Utf16Le {
+                        u16::from_le_bytes(pair)
+                    } else {
+                        u16::from_be_bytes(pair)
+                    }
+                });
+                let text = char::decode_utf16(units)
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect::<String>();
+                text.strip_prefix('\u{feff}')
+                    .map_or_else(|| text.clone(), ToString::to_string)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_is_unchanged_utf8() {
+        let text = "a\r\nb\nc";
+        assert_eq!(Output::default().encode(text), text.as_bytes());
+    }
+
+    #[test]
+    fn normalizes_line_endings() {
+        let crlf = Output {
+            line_endings: Some(LineEnding::Crlf),
+            ..Output::default()
+        };
+        assert_eq!(crlf.encode("a\r\nb\nc"), b"a\r\nb\r\nc");
+
+        let lf = Output {
+            line_endings: Some(LineEnding::Lf),
+            ..Output::default()
+        };
+        assert_eq!(lf.encode("a\r\nb\nc"), b"a\nb\nc");
+    }
+
+    #[test]
+    fn utf16_with_bom() {
+        let le = Output {
+            encoding: Encoding::Utf16Le,
+            bom: true,
+            line_endings: None,
+        };
+        assert_eq!(le.encode("hé"), b"\xFF\xFEh\x00\xE9\x00");
+
+        let be = Output {
+            encoding: Encoding::Utf16Be,
+            ..le
+        };
+        assert_eq!(be.encode("hé"), b"\xFE\xFF\x00h\x00\xE9");
+
+        assert_eq!(le.decode(&le.encode("hé")), "hé");
+        assert_eq!(be.decode(&be.encode("hé")), "hé");
+    }
+}
@@ -5,7 +5,7 @@ use std::{
 
 use base64::Engine as _;
 
-use crate::models::Color;
+use crate::{colorspace::Lch, format, models::Color, simulation::Deficiency};
 
 pub fn mix(
     value: &tera::Value,
@@ -23,17 +23,89 @@ pub fn mix(
         .as_f64()
         .ok_or_else(|| tera::Error::msg("blend amount must be a number"))?;
 
-    let result = Color::mix(&base, &blend, amount);
+    let result = match color_space(args, "rgb")? {
+        None => Color::mix(&base, &blend, amount),
+        Some(PerceptualSpace::Oklab) => Color::mix_oklab(&base, &blend, amount),
+        Some(PerceptualSpace::Oklch) => Color::mix_oklch(&base, &blend, amount),
+    };
 
     Ok(tera::to_value(result)?)
 }
 
+#[derive(Clone, Copy)]
+enum PerceptualSpace {
+    Oklab,
+    Oklch,
+}
+
+/// read the `space` argument of a filter, returning `None` for the filter's
+/// default (non-perceptual) color space.
+fn color_space(
+    args: &HashMap<String, tera::Value>,
+    default: &str,
+) -> Result<Option<PerceptualSpace>, tera::Error> {
+    let Some(space) = args.get("space") else {
+        return Ok(None);
+    };
+    match space.as_str() {
+        Some("oklab") => Ok(Some(PerceptualSpace::Oklab)),
+        Some("oklch") => Ok(Some(PerceptualSpace::Oklch)),
+        Some(s) if s == default => Ok(None),
+        _ => Err(tera::Error::msg(format!(
+            "space must be one of \"{default}\", \"oklab\" or \"oklch\""
+        ))),
+    }
+}
+
+/// apply `op` to the lightness, chroma, or hue of `color` in OKLCH space.
+///
+/// returns `None` if the filter wasn't asked to work in a perceptual space, or
+/// if the argument being modified doesn't depend on the color space.
+fn modify_perceptual(
+    color: &Color,
+    args: &HashMap<String, tera::Value>,
+    op: impl Fn(f64, f64) -> f64,
+) -> Result<Option<Color>, tera::Error> {
+    if color_space(args, "hsl")?.is_none() {
+        return Ok(None);
+    }
+
+    if let Some(lightness) = args.get("lightness") {
+        // lightness is a percentage, like it is in HSL space.
+        let lightness: f64 = tera::from_value(lightness.clone())?;
+        Ok(Some(color.map_oklch(|lch| Lch {
+            l: op(lch.l, lightness / 100.0),
+            ..lch
+        })))
+    } else if let Some(chroma) = args.get("chroma") {
+        let chroma: f64 = tera::from_value(chroma.clone())?;
+        Ok(Some(color.map_oklch(|lch| Lch {
+            c: op(lch.c, chroma),
+            ..lch
+        })))
+    } else if let Some(hue) = args.get("hue") {
+        let hue: f64 = tera::from_value(hue.clone())?;
+        Ok(Some(color.map_oklch(|lch| Lch {
+            h: op(lch.h, hue).rem_euclid(360.0),
+            ..lch
+        })))
+    } else if args.contains_key("saturation") {
+        Err(tera::Error::msg(
+            "saturation is not available in perceptual color spaces, use chroma instead",
+        ))
+    } else {
+        Ok(None)
+    }
+}
+
 pub fn modify(
     value: &tera::Value,
     args: &HashMap<String, tera::Value>,
 ) -> Result<tera::Value, tera::Error> {
     let color: Color = tera::from_value(value.clone())?;
-    if let Some(hue) = args.get("hue") {
+    if let Some(color) = modify_perceptual(&color, args, |_, value| value)? {
+        Ok(tera::to_value(color)?)
+    } else if let Some(hue) = args.get("hue") {
         let hue = tera::from_value(hue.clone())?;
         Ok(tera::to_value(color.mod_hue(hue))?)
     } else if let Some(saturation) = args.get("saturation") {
@@ -55,7 +127,9 @@ pub fn add(
     args: &HashMap<String, tera::Value>,
 ) -> Result<tera::Value, tera::Error> {
     let color: Color = tera::from_value(value.clone())?;
-    if let Some(hue) = args.get("hue") {
+    if let Some(color) = modify_perceptual(&color, args, |current, value| current + value)? {
+        Ok(tera::to_value(color)?)
+    } else if let Some(hue) = args.get("hue") {
         let hue = tera::from_value(hue.clone())?;
         Ok(tera::to_value(color.add_hue(hue))?)
     } else if let Some(saturation) = args.get("saturation") {
@@ -77,7 +151,9 @@ pub fn sub(
     args: &HashMap<String, tera::Value>,
 ) -> Result<tera::Value, tera::Error> {
     let color: Color = tera::from_value(value.clone())?;
-    if let Some(hue) = args.get("hue") {
+    if let Some(color) = modify_perceptual(&color, args, |current, value| current - value)? {
+        Ok(tera::to_value(color)?)
+    } else if let Some(hue) = args.get("hue") {
         let hue = tera::from_value(hue.clone())?;
         Ok(tera::to_value(color.sub_hue(hue))?)
     } else if let Some(saturation) = args.get("saturation") {
@@ -94,6 +170,52 @@ pub fn sub(
     }
 }
 
+pub fn simulate(
+    value: &tera::Value,
+    args: &HashMap<String, tera::Value>,
+) -> Result<tera::Value, tera::Error> {
+    let color: Color = tera::from_value(value.clone())?;
+    let deficiency: Deficiency = tera::from_value(
+        args.get("deficiency")
+            .ok_or_else(|| tera::Error::msg("deficiency is required"))?
+            .clone(),
+    )?;
+    let severity = args
+        .get("severity")
+        .map(|severity| {
+            severity
+                .as_f64()
+                .ok_or_else(|| tera::Error::msg("severity must be a number"))
+        })
+        .transpose()?
+        .unwrap_or(1.0);
+
+    Ok(tera::to_value(color.simulate(deficiency, severity))?)
+}
+
+pub fn format(
+    value: &tera::Value,
+    args: &HashMap<String, tera::Value>,
+) -> Result<tera::Value, tera::Error> {
+    let color: Color = tera::from_value(value.clone())?;
+    let pattern = match (args.get("preset"), args.get("pattern")) {
+        (Some(preset), None) => {
+            let name: String = tera::from_value(preset.clone())?;
+            format::preset(&name).map_err(|e| tera::Error::msg(e.to_string()))?
+        }
+        (None, Some(pattern)) => pattern
+            .as_str()
+            .ok_or_else(|| tera::Error::msg("pattern must be a string"))?,
+        _ => {
+            return Err(tera::Error::msg(
+                "exactly one of preset or pattern is required",
+            ))
+        }
+    };
+    let formatted = format::format(&color, pattern).map_err(|e| tera::Error::msg(e.to_string()))?;
+    Ok(tera::to_value(formatted)?)
+}
+
 pub fn urlencode_lzma(
     value: &tera::Value,
     _args: &HashMap<String, tera::Value>,
@@ -0,0 +1,262 @@
+//! formatting of colors for platform-specific syntaxes, with named presets
+//! and a pattern language for everything else.
+//!
+//! a pattern is literal text with placeholders in braces, e.g.
+//! `0x{a:X}{r:X}{g:X}{b:X}`. `{{` and `}}` are literal braces.
+
+use crate::{colorspace::Lab, models::Color};
+
+/// Named patterns for common syntaxes.
+pub const PRESETS: [(&str, &str); 12] = [
+    // #RRGGBBAA, as in CSS
+    ("rgba_hex", "#{r:x}{g:x}{b:x}{a:x}"),
+    // #AARRGGBB, as in Android resources
+    ("argb_hex", "#{a:x}{r:x}{g:x}{b:x}"),
+    // 0xAARRGGBB, as in Android and Windows code
+    ("argb_int", "0x{a:X}{r:X}{g:X}{b:X}"),
+    // normalized floats, as in Xcode and iTerm2 property lists
+    ("float_rgb", "{r:.3} {g:.3} {b:.3}"),
+    ("float_rgba", "{r:.3} {g:.3} {b:.3} {a:.3}"),
+    // 16 bits per channel
+    ("rgb16", "{r:16} {g:16} {b:16}"),
+    ("x11", "rgb:{r:x16}/{g:x16}/{b:x16}"),
+    // CSS Color 4 space-separated syntax
+    ("css4_rgb", "rgb({r} {g} {b})"),
+    ("css4_rgba", "rgb({r} {g} {b} / {a:.2})"),
+    ("css4_hsl", "hsl({h} {s}% {l}%)"),
+    // nearest terminal colors
+    ("ansi256", "{ansi256}"),
+    ("ansi16", "{ansi16}"),
+];
+
+/// The default colors of the 16 ANSI colors in xterm.
+const XTERM_16: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00],
+    [0xcd, 0x00, 0x00],
+    [0x00, 0xcd, 0x00],
+    [0xcd, 0xcd, 0x00],
+    [0x00, 0x00, 0xee],
+    [0xcd, 0x00, 0xcd],
+    [0x00, 0xcd, 0xcd],
+    [0xe5, 0xe5, 0xe5],
+    [0x7f, 0x7f, 0x7f],
+    [0xff, 0x00, 0x00],
+    [0x00, 0xff, 0x00],
+    [0xff, 0xff, 0x00],
+    [0x5c, 0x5c, 0xff],
+    [0xff, 0x00, 0xff],
+    [0x00, 0xff, 0xff],
+    [0xff, 0xff, 0xff],
+];
+
+/// The levels of each channel in xterm's 6x6x6 color cube.
+const XTERM_CUBE: [u8; 6] = [0x00, 0x5f, 0x87, 0xaf, 0xd7, 0xff];
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Unknown preset {0}, expected one of {names}", names = preset_names())]
+    UnknownPreset(String),
+
+    #[error("Unknown placeholder {{{0}}}")]
+    UnknownPlaceholder(String),
+
+    #[error("Placeholder {{{0}}} has an invalid format")]
+    InvalidSpec(String),
+
+    #[error("Placeholder is missing its closing brace")]
+    Unclosed,
+
+    #[error("Closing brace without an opening one, use }}}} for a literal brace")]
+    Unmatched,
+}
+
+fn preset_names() -> String {
+    PRESETS.map(|(name, _)| name).join(", ")
+}
+
+/// The pattern of a named preset.
+pub fn preset(name: &str) -> Result<&'static str, Error> {
+    PRESETS
+        .iter()
+        .find(|(preset, _)| *preset == name)
+        .map(|(_, pattern)| *pattern)
+        .ok_or_else(|| Error::UnknownPreset(name.to_string()))
+}
+
+/// Format `color` with a pattern.
+///
+/// The placeholders are:
+///
+/// - `{r}`, `{g}`, `{b}` and `{a}` for a channel or the opacity, from 0 to
+///   255. They can be formatted as two hex digits with `:x` or `:X`, as a
+///   16-bit number with `:16`, as four hex digits with `:x16` or `:X16`, as a
+///   percentage with `:%`, or as a number from 0 to 1 with `:.N` for N
+///   decimal places.
+/// - `{h}` for the HSL hue in degrees, with `:.N` for decimal places.
+/// - `{s}` and `{l}` for the HSL saturation and lightness as percentages,
+///   or from 0 to 1 with `:.N`.
+/// - `{ansi256}` for the nearest of xterm's 256 colors, excluding the 16
+///   ANSI colors that terminals change, and `{ansi16}` for the nearest ANSI
+///   color with xterm's defaults.
+pub fn format(color: &Color, pattern: &str) -> Result<String, Error> {
+    let mut out = String::new();
+    let mut rest = pattern;
+    while let Some(i) = rest.find(['{', '}']) {
+        out.push_str(&rest[..i]);
+        let brace = &rest[i..=i];
+        rest = &rest[i + 1..];
+        if let Some(after) = rest.strip_prefix(brace) {
+            out.push_str(brace);
+            rest = after;
+        } else if brace == "}" {
+            return Err(Error::Unmatched);
+        } else {
+            let end = rest.find('}').ok_or(Error::Unclosed)?;
+            out.push_str(&placeholder(color, &rest[..end])?);
+            rest = &rest[end + 1..];
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn placeholder(color: &Color, placeholder: &str) -> Result<String, Error> {
+    let (name, spec) = placeholder.split_once(':').unwrap_or((placeholder, ""));
+    let places = spec.strip_prefix('.').and_then(|p| p.parse::<usize>().ok());
+    let invalid = || Error::InvalidSpec(placeholder.to_string());
+
+    let channel = match name {
+        "r" => color.rgb.r,
+        "g" => color.rgb.g,
+        "b" => color.rgb.b,
+        "a" => color.opacity,
+        "h" => {
+            let hue = f64::from(color.hsl.h);
+            return match (spec, places) {
+                ("", _) => Ok(color.hsl.h.to_string()),
+                (_, Some(places)) => Ok(format!("{hue:.places$}")),
+                _ => Err(invalid()),
+            };
+        }
+        "s" | "l" => {
+            let ratio = f64::from(if name == "s" {
+                color.hsl.s
+            } else {
+                color.hsl.l
+            });
+            return match (spec, places) {
+                ("" | "%", _) => Ok(format!("{}", (ratio * 100.0).round())),
+                (_, Some(places)) => Ok(format!("{ratio:.places$}")),
+                _ => Err(invalid()),
+            };
+        }
+        "ansi256" if spec.is_empty() => return Ok(ansi256(color).to_string()),
+        "ansi16" if spec.is_empty() => return Ok(ansi16(color).to_string()),
+        "ansi256" | "ansi16" => return Err(invalid()),
+        _ => return Err(Error::UnknownPlaceholder(placeholder.to_string())),
+    };
+
+    let wide = u16::from(channel) * 257;
+    let ratio = f64::from(channel) / 255.0;
+    match (spec, places) {
+        ("", _) => Ok(channel.to_string()),
+        ("x", _) => Ok(format!("{channel:02x}")),
+        ("X", _) => Ok(format!("{channel:02X}")),
+        ("16", _) => Ok(wide.to_string()),
+        ("x16", _) => Ok(format!("{wide:04x}")),
+        ("X16", _) => Ok(format!("{wide:04X}")),
+        ("%", _) => Ok(format!("{}", (ratio * 100.0).round())),
+        (_, Some(places)) => Ok(format!("{ratio:.places$}")),
+        _ => Err(invalid()),
+    }
+}
+
+/// The index of the nearest of xterm's 256 colors, from 16 to 255.
+fn ansi256(color: &Color) -> u8 {
+    let cube = (0..216).map(|i| {
+        let level = |n: usize| XTERM_CUBE[n % 6];
+        (16 + i as u8, [level(i / 36), level(i / 6), level(i)])
+    });
+    let grays = (0..24).map(|i| {
+        let level = 8 + 10 * i;
+        (232 + i, [level; 3])
+    });
+    nearest(color, cube.chain(grays))
+}
+
+/// The index of the nearest ANSI color, from 0 to 15.
+fn ansi16(color: &Color) -> u8 {
+    nearest(color, (0..).zip(XTERM_16))
+}
+
+/// The index of the candidate nearest to `color` in `OKLab` space.
+fn nearest(color: &Color, candidates: impl Iterator<Item = (u8, [u8; 3])>) -> u8 {
+    let target = Lab::from_rgb(color.rgb.r, color.rgb.g, color.rgb.b);
+    let distance = |[r, g, b]: [u8; 3]| {
+        let lab = Lab::from_rgb(r, g, b);
+        (lab.l - target.l)
+            .hypot(lab.a - target.a)
+            .hypot(lab.b - target.b)
+    };
+    candidates
+        .min_by(|(_, a), (_, b)| distance(*a).total_cmp(&distance(*b)))
+        .map_or(0, |(index, _)| index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn red() -> Color {
+        let palette =
+            crate::models::build_palette(false, None, None, None).expect("palette can be built");
+        palette.flavors["latte"].colors["red"].clone()
+    }
+
+    #[test]
+    fn presets() {
+        let red = red();
+        let formatted =
+            |name| format(&red, preset(name).expect("preset exists")).expect("preset is valid");
+        assert_eq!(formatted("rgba_hex"), "#d20f39ff");
+        assert_eq!(formatted("argb_hex"), "#ffd20f39");
+        assert_eq!(formatted("argb_int"), "0xFFD20F39");
+        assert_eq!(formatted("float_rgb"), "0.824 0.059 0.224");
+        assert_eq!(formatted("rgb16"), "53970 3855 14649");
+        assert_eq!(formatted("x11"), "rgb:d2d2/0f0f/3939");
+        assert_eq!(formatted("css4_rgba"), "rgb(210 15 57 / 1.00)");
+        assert_eq!(formatted("css4_hsl"), "hsl(347 87% 44%)");
+        assert_eq!(formatted("ansi256"), "160");
+        assert_eq!(formatted("ansi16"), "1");
+    }
+
+    #[test]
+    #[allow(clippy::literal_string_with_formatting_args)]
+    fn patterns() {
+        let red = red();
+        assert_eq!(
+            format(&red, "{{ {r:%}% {h:.1} {l:.2} }}").expect("pattern is valid"),
+            "{ 82% 347.0 0.44 }"
+        );
+        assert!(matches!(
+            format(&red, "{q}"),
+            Err(Error::UnknownPlaceholder(p)) if p == "q"
+        ));
+        assert!(matches!(format(&red, "{h:x}"), Err(Error::InvalidSpec(_))));
+        assert!(matches!(format(&red, "{r"), Err(Error::Unclosed)));
+        assert!(matches!(format(&red, "r}"), Err(Error::Unmatched)));
+        assert!(matches!(preset("hex"), Err(Error::UnknownPreset(_))));
+    }
+
+    #[test]
+    fn nearest_terminal_colors() {
+        let mut white = red();
+        white.rgb = crate::models::RGB {
+            r: 255,
+            g: 255,
+            b: 255,
+        };
+        assert_eq!(ansi256(&white), 231);
+        assert_eq!(ansi16(&white), 15);
+    }
+}
@@ -36,7 +36,23 @@ pub fn parse(input: &str) -> Result<Document, Error> {
     })
 }
 
-fn split(template: &str) -> Option<(&str, &str)> {
+/// The lines that the frontmatter and the body start on within `input`,
+/// counting from zero, to locate errors in either within the whole template.
+#[must_use]
+pub fn line_offsets(input: &str) -> (usize, usize) {
+    let Some((frontmatter, body)) = split(input) else {
+        return (0, 0);
+    };
+    let line = |part: &str| {
+        let start = part.as_ptr() as usize - input.as_ptr() as usize;
+        input[..start].matches('\n').count()
+    };
+    (line(frontmatter), line(body))
+}
+
+/// Split a template into its frontmatter and body, if it has frontmatter.
+#[must_use]
+pub fn split(template: &str) -> Option<(&str, &str)> {
     // we consider a template to possibly have frontmatter iff:
     // * line 0 is "---"
     // * there is another "---" on another line
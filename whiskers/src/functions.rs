@@ -67,3 +67,77 @@ pub fn css_hsla(args: &HashMap<String, tera::Value>) -> Result<tera::Value, tera
     let color: css_colors::HSLA = (&color).into();
     Ok(tera::to_value(color.to_string())?)
 }
+
+pub fn contrast_ratio(args: &HashMap<String, tera::Value>) -> Result<tera::Value, tera::Error> {
+    let a: Color = tera::from_value(
+        args.get("a")
+            .ok_or_else(|| tera::Error::msg("a is required"))?
+            .clone(),
+    )?;
+    let b: Color = tera::from_value(
+        args.get("b")
+            .ok_or_else(|| tera::Error::msg("b is required"))?
+            .clone(),
+    )?;
+    Ok(tera::to_value(a.contrast_ratio(&b))?)
+}
+
+pub fn apca(args: &HashMap<String, tera::Value>) -> Result<tera::Value, tera::Error> {
+    let fg: Color = tera::from_value(
+        args.get("fg")
+            .ok_or_else(|| tera::Error::msg("fg is required"))?
+            .clone(),
+    )?;
+    let bg: Color = tera::from_value(
+        args.get("bg")
+            .ok_or_else(|| tera::Error::msg("bg is required"))?
+            .clone(),
+    )?;
+    Ok(tera::to_value(fg.apca_contrast(&bg))?)
+}
+
+pub fn readable_on(args: &HashMap<String, tera::Value>) -> Result<tera::Value, tera::Error> {
+    let bg: Color = tera::from_value(
+        args.get("bg")
+            .ok_or_else(|| tera::Error::msg("bg is required"))?
+            .clone(),
+    )?;
+    let candidates: Vec<Color> = tera::from_value(
+        args.get("candidates")
+            .ok_or_else(|| tera::Error::msg("candidates is required"))?
+            .clone(),
+    )?;
+    let min = args
+        .get("min")
+        .map(|min| {
+            min.as_f64()
+                .ok_or_else(|| tera::Error::msg("min must be a number"))
+        })
+        .transpose()?;
+
+    // APCA contrast is signed by polarity, so compare magnitudes instead.
+    let contrast = |color: &Color| match args.get("algorithm").and_then(tera::Value::as_str) {
+        Some("apca") => Ok(color.apca_contrast(&bg).abs()),
+        Some("wcag") | None => Ok(color.contrast_ratio(&bg)),
+        Some(_) => Err(tera::Error::msg("algorithm must be \"wcag\" or \"apca\"")),
+    };
+
+    // with a minimum, prefer the first candidate that meets it. otherwise (or
+    // if none do) pick the candidate with the highest contrast.
+    if let Some(min) = min {
+        for candidate in &candidates {
+            if contrast(candidate)? >= min {
+                return Ok(tera::to_value(candidate)?);
+            }
+        }
+    }
+    let mut best: Option<(f64, &Color)> = None;
+    for candidate in &candidates {
+        let contrast = contrast(candidate)?;
+        if best.is_none_or(|(best, _)| contrast > best) {
+            best = Some((contrast, candidate));
+        }
+    }
+    let (_, best) = best.ok_or_else(|| tera::Error::msg("candidates must not be empty"))?;
+    Ok(tera::to_value(best)?)
+}
@@ -1,9 +1,26 @@
+pub mod audit;
+pub mod check;
 pub mod cli;
+pub mod colorspace;
+pub mod config;
 pub mod context;
+pub mod contrast;
+pub mod diagnostic;
+pub mod encoding;
 pub mod filters;
+pub mod format;
 pub mod frontmatter;
 pub mod functions;
+#[cfg(not(target_family = "wasm"))]
+pub mod lsp;
+pub mod manifest;
 pub mod markdown;
 pub mod matrix;
 pub mod models;
+pub mod render;
+pub mod simulation;
 pub mod templating;
+pub mod testing;
+pub mod validate;
+#[cfg(target_family = "wasm")]
+mod wasm;
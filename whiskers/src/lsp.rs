@@ -0,0 +1,680 @@
+//! a language server for templates, started with `whiskers lsp`.
+//!
+//! It completes context variables, filters and functions inside tags, shows
+//! the documentation of filters and functions on hover, shows swatches for
+//! hex color literals, and reports frontmatter and syntax errors.
+
+use std::{collections::HashMap, fmt::Write as _, path::Path};
+
+use itertools::Itertools as _;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+        PublishDiagnostics,
+    },
+    request::{ColorPresentationRequest, Completion, DocumentColor, HoverRequest, Request as _},
+    Color, ColorInformation, ColorPresentation, ColorProviderCapability, CompletionItem,
+    CompletionItemKind, CompletionOptions, CompletionResponse, Diagnostic, DiagnosticSeverity,
+    Documentation, Hover, HoverContents, HoverProviderCapability, MarkupContent, MarkupKind,
+    Position, PublishDiagnosticsParams, Range, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, Url,
+};
+
+use crate::{frontmatter, matrix, models, render, templating, validate};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Language server protocol error")]
+    Protocol(#[from] lsp_server::ProtocolError),
+
+    #[error("Client disconnected from the language server")]
+    Disconnected,
+
+    #[error("Language server capabilities could not be serialized")]
+    Capabilities(#[from] serde_json::Error),
+
+    #[error("Language server I/O failed")]
+    Io(#[from] std::io::Error),
+}
+
+/// Serve on stdin and stdout until the client shuts the server down.
+pub fn run() -> Result<(), Error> {
+    let (connection, io_threads) = Connection::stdio();
+    connection.initialize(serde_json::to_value(capabilities())?)?;
+    Server::default().serve(&connection)?;
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![".".to_string(), "|".to_string()]),
+            ..CompletionOptions::default()
+        }),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        color_provider: Some(ColorProviderCapability::Simple(true)),
+        ..ServerCapabilities::default()
+    }
+}
+
+/// The open documents, which are fully synced on every change.
+#[derive(Default)]
+struct Server {
+    documents: HashMap<Url, String>,
+}
+
+impl Server {
+    fn serve(&mut self, connection: &Connection) -> Result<(), Error> {
+        for message in &connection.receiver {
+            match message {
+                Message::Request(request) => {
+                    if connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    let response = self.handle_request(request);
+                    connection
+                        .sender
+                        .send(response.into())
+                        .map_err(|_| Error::Disconnected)?;
+                }
+                Message::Notification(notification) => {
+                    if let Some(params) = self.handle_notification(notification) {
+                        connection
+                            .sender
+                            .send(
+                                Notification::new(PublishDiagnostics::METHOD.to_string(), params)
+                                    .into(),
+                            )
+                            .map_err(|_| Error::Disconnected)?;
+                    }
+                }
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_request(&self, request: Request) -> Response {
+        match request.method.as_str() {
+            Completion::METHOD => respond::<Completion>(request, |params| {
+                let position = params.text_document_position;
+                self.documents
+                    .get(&position.text_document.uri)
+                    .map(|text| CompletionResponse::Array(completions(text, position.position)))
+            }),
+            HoverRequest::METHOD => respond::<HoverRequest>(request, |params| {
+                let position = params.text_document_position_params;
+                self.documents
+                    .get(&position.text_document.uri)
+                    .and_then(|text| hover(text, position.position))
+            }),
+            DocumentColor::METHOD => respond::<DocumentColor>(request, |params| {
+                self.documents
+                    .get(&params.text_document.uri)
+                    .map(|text| document_colors(text))
+                    .unwrap_or_default()
+            }),
+            ColorPresentationRequest::METHOD => {
+                respond::<ColorPresentationRequest>(request, |params| {
+                    color_presentations(params.color)
+                })
+            }
+            _ => Response::new_err(
+                request.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("Unsupported method {}", request.method),
+            ),
+        }
+    }
+
+    /// Track document changes, returning the diagnostics to publish for the
+    /// changed document.
+    fn handle_notification(
+        &mut self,
+        notification: Notification,
+    ) -> Option<PublishDiagnosticsParams> {
+        let (uri, text) = match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params = params::<DidOpenTextDocument>(notification)?;
+                (params.text_document.uri, params.text_document.text)
+            }
+            DidChangeTextDocument::METHOD => {
+                let mut params = params::<DidChangeTextDocument>(notification)?;
+                (params.text_document.uri, params.content_changes.pop()?.text)
+            }
+            DidCloseTextDocument::METHOD => {
+                let params = params::<DidCloseTextDocument>(notification)?;
+                let uri = params.text_document.uri;
+                self.documents.remove(&uri);
+                return Some(PublishDiagnosticsParams::new(uri, vec![], None));
+            }
+            _ => return None,
+        };
+
+        let path = uri.to_file_path().ok();
+        let diagnostics = diagnostics(&text, path.as_deref());
+        self.documents.insert(uri.clone(), text);
+        Some(PublishDiagnosticsParams::new(uri, diagnostics, None))
+    }
+}
+
+fn params<N: lsp_types::notification::Notification>(
+    notification: Notification,
+) -> Option<N::Params> {
+    notification.extract(N::METHOD).ok()
+}
+
+/// Respond to a request of type `R` with the result of `handler`.
+fn respond<R: lsp_types::request::Request>(
+    request: Request,
+    handler: impl FnOnce(R::Params) -> R::Result,
+) -> Response {
+    let id = request.id.clone();
+    match request.extract::<R::Params>(R::METHOD) {
+        Ok((id, params)) => Response::new_ok(id, handler(params)),
+        Err(e) => Response::new_err(id, ErrorCode::InvalidParams as i32, e.to_string()),
+    }
+}
+
+/// Completions at `position`, which are only offered inside `{{ }}` and
+/// `{% %}` tags in the template body.
+#[must_use]
+pub fn completions(text: &str, position: Position) -> Vec<CompletionItem> {
+    let (_, body_line) = frontmatter::line_offsets(text);
+    if (position.line as usize) < body_line {
+        return vec![];
+    }
+    let before = &text[..offset(text, position)];
+    let tag_start = before.rfind("{{").max(before.rfind("{%"));
+    let tag_end = before.rfind("}}").max(before.rfind("%}"));
+    let Some(tag_start) = tag_start.filter(|&start| tag_end.is_none_or(|end| end < start)) else {
+        return vec![];
+    };
+
+    let expr = &before[tag_start + 2..];
+    let word_start = expr
+        .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+        .map_or(0, |i| i + 1);
+    let word = &expr[word_start..];
+    if expr[..word_start].trim_end().ends_with('|') {
+        return templating::all_filters()
+            .into_iter()
+            .map(|filter| CompletionItem {
+                label: filter.name.clone(),
+                kind: Some(CompletionItemKind::FUNCTION),
+                detail: Some(filter_signature(&filter)),
+                documentation: Some(markdown(filter_docs(&filter))),
+                ..CompletionItem::default()
+            })
+            .collect();
+    }
+
+    let ctx = sample_context(text);
+    if let Some((path, _)) = word.rsplit_once('.') {
+        let mut value = &ctx;
+        for key in path.split('.') {
+            let Some(field) = value.get(key) else {
+                return vec![];
+            };
+            value = field;
+        }
+        return value.as_object().map_or_else(Vec::new, |fields| {
+            fields
+                .iter()
+                .map(|(key, value)| variable(key, value, CompletionItemKind::FIELD))
+                .collect()
+        });
+    }
+
+    let variables = ctx
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(key, value)| variable(key, value, CompletionItemKind::VARIABLE));
+    let functions = templating::all_functions()
+        .into_iter()
+        .map(|function| CompletionItem {
+            label: function.name.clone(),
+            kind: Some(CompletionItemKind::FUNCTION),
+            detail: Some(function_signature(&function)),
+            documentation: Some(markdown(function_docs(&function))),
+            ..CompletionItem::default()
+        });
+    variables.chain(functions).collect()
+}
+
+/// A completion for a context variable. Colors are shown with a swatch.
+fn variable(key: &str, value: &serde_json::Value, kind: CompletionItemKind) -> CompletionItem {
+    if let Some(hex) = value.get("hex").and_then(serde_json::Value::as_str) {
+        return CompletionItem {
+            label: key.to_string(),
+            kind: Some(CompletionItemKind::COLOR),
+            detail: value
+                .get("name")
+                .and_then(serde_json::Value::as_str)
+                .map(ToString::to_string),
+            documentation: Some(Documentation::String(format!("#{hex}"))),
+            ..CompletionItem::default()
+        };
+    }
+    let detail = match value {
+        serde_json::Value::Object(_) => "object".to_string(),
+        serde_json::Value::Array(_) => "array".to_string(),
+        value => value.to_string(),
+    };
+    CompletionItem {
+        label: key.to_string(),
+        kind: Some(kind),
+        detail: Some(detail),
+        ..CompletionItem::default()
+    }
+}
+
+/// A sample of the context that the template is rendered with, using the
+/// first flavor and the first value of every matrix iterable.
+fn sample_context(text: &str) -> serde_json::Value {
+    let palette = models::build_palette(false, None, None, None)
+        .expect("the Catppuccin palette is always valid");
+    let doc_frontmatter = frontmatter::parse(text)
+        .map(|doc| doc.frontmatter)
+        .unwrap_or_default();
+
+    let mut ctx = serde_json::Map::new();
+    for (key, value) in &doc_frontmatter {
+        ctx.insert(key.clone(), value.clone());
+    }
+    ctx.insert(
+        "flavors".to_string(),
+        serde_json::to_value(&palette.flavors).expect("palette is always serializable"),
+    );
+    if let Some((_, flavor)) = palette.flavors.first() {
+        ctx.insert(
+            "flavor".to_string(),
+            serde_json::to_value(flavor).expect("flavor is always serializable"),
+        );
+        for (identifier, color) in &flavor.colors {
+            ctx.insert(
+                identifier.clone(),
+                serde_json::to_value(color).expect("color is always serializable"),
+            );
+        }
+    }
+
+    let matrix = doc_frontmatter
+        .get("whiskers")
+        .and_then(|opts| opts.get("matrix"))
+        .and_then(|m| m.as_array())
+        .and_then(|m| matrix::from_values(m.clone(), models::flavor_identifiers(None)).ok());
+    if let Some(matrix) = matrix {
+        let length = matrix.combinations().len();
+        ctx.insert(
+            "matrix".to_string(),
+            serde_json::json!({
+                "index": 1,
+                "index0": 0,
+                "first": true,
+                "last": length == 1,
+                "length": length,
+            }),
+        );
+        for (key, values) in matrix.iterables {
+            if let Some(value) = values.into_iter().next().filter(|_| key != "flavor") {
+                ctx.insert(key, value.into());
+            }
+        }
+    }
+    ctx.remove("whiskers");
+    serde_json::Value::Object(ctx)
+}
+
+/// Documentation of the filter or function under `position`.
+#[must_use]
+pub fn hover(text: &str, position: Position) -> Option<Hover> {
+    let offset = offset(text, position);
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let start = text[..offset]
+        .rfind(|c: char| !is_word(c))
+        .map_or(0, |i| i + 1);
+    let end = text[offset..]
+        .find(|c: char| !is_word(c))
+        .map_or(text.len(), |i| offset + i);
+    let word = &text[start..end];
+    if word.is_empty() {
+        return None;
+    }
+
+    let docs = if text[..start].trim_end().ends_with('|') {
+        templating::all_filters()
+            .into_iter()
+            .find(|filter| filter.name == word)
+            .map(|filter| {
+                format!(
+                    "```\n{}\n```\n\n{}",
+                    filter_signature(&filter),
+                    filter_docs(&filter)
+                )
+            })
+    } else if text[end..].trim_start().starts_with('(') {
+        templating::all_functions()
+            .into_iter()
+            .find(|function| function.name == word)
+            .map(|function| {
+                format!(
+                    "```\n{}\n```\n\n{}",
+                    function_signature(&function),
+                    function_docs(&function)
+                )
+            })
+    } else {
+        None
+    }?;
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: docs,
+        }),
+        range: Some(Range::new(
+            lsp_position(text, start),
+            lsp_position(text, end),
+        )),
+    })
+}
+
+/// Every argument used in the examples, which is as close to a signature as
+/// the filters and functions have.
+fn arguments<'a>(inputs: impl Iterator<Item = &'a String>) -> String {
+    inputs.unique().join(", ")
+}
+
+fn filter_signature(filter: &templating::Filter) -> String {
+    let arguments = arguments(filter.examples.iter().flat_map(|e| e.inputs.keys()));
+    if arguments.is_empty() {
+        filter.name.clone()
+    } else {
+        format!("{}({arguments})", filter.name)
+    }
+}
+
+fn function_signature(function: &templating::Function) -> String {
+    let arguments = arguments(function.examples.iter().flat_map(|e| e.inputs.keys()));
+    format!("{}({arguments})", function.name)
+}
+
+fn filter_docs(filter: &templating::Filter) -> String {
+    let mut docs = format!("{}\n\n", filter.description);
+    for example in &filter.examples {
+        let inputs = example
+            .inputs
+            .iter()
+            .map(|(k, v)| format!("{k}={v}"))
+            .join(", ");
+        let call = if inputs.is_empty() {
+            filter.name.clone()
+        } else {
+            format!("{}({inputs})", filter.name)
+        };
+        let _ = writeln!(
+            docs,
+            "- `{} | {call}` => `{}`",
+            example.value, example.output
+        );
+    }
+    docs
+}
+
+fn function_docs(function: &templating::Function) -> String {
+    let mut docs = format!("{}\n\n", function.description);
+    for example in &function.examples {
+        let inputs = example
+            .inputs
+            .iter()
+            .map(|(k, v)| format!("{k}={v}"))
+            .join(", ");
+        let _ = writeln!(
+            docs,
+            "- `{}({inputs})` => `{}`",
+            function.name, example.output
+        );
+    }
+    docs
+}
+
+const fn markdown(value: String) -> Documentation {
+    Documentation::MarkupContent(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value,
+    })
+}
+
+/// Every `#rrggbb` and `#rrggbbaa` literal in the document.
+#[must_use]
+pub fn document_colors(text: &str) -> Vec<ColorInformation> {
+    let bytes = text.as_bytes();
+    let mut colors = vec![];
+    for (start, _) in text.match_indices('#') {
+        let digits = bytes[start + 1..]
+            .iter()
+            .take_while(|b| b.is_ascii_hexdigit())
+            .count();
+        // `&#123;` is an HTML character reference, not a color
+        if !matches!(digits, 6 | 8) || start.checked_sub(1).map(|i| bytes[i]) == Some(b'&') {
+            continue;
+        }
+        let hex = &text[start + 1..=start + digits];
+        let channel =
+            |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_or(0.0, |v| f32::from(v) / 255.0);
+        colors.push(ColorInformation {
+            range: Range::new(
+                lsp_position(text, start),
+                lsp_position(text, start + 1 + digits),
+            ),
+            color: Color {
+                red: channel(0),
+                green: channel(2),
+                blue: channel(4),
+                alpha: if digits == 8 { channel(6) } else { 1.0 },
+            },
+        });
+    }
+    colors
+}
+
+/// The hex literal for a color picked in the editor, which only includes the
+/// alpha channel if the color is translucent.
+#[must_use]
+pub fn color_presentations(color: Color) -> Vec<ColorPresentation> {
+    let channel = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let mut hex = format!(
+        "#{:02x}{:02x}{:02x}",
+        channel(color.red),
+        channel(color.green),
+        channel(color.blue)
+    );
+    if channel(color.alpha) < u8::MAX {
+        let _ = write!(hex, "{:02x}", channel(color.alpha));
+    }
+    vec![ColorPresentation {
+        label: hex,
+        text_edit: None,
+        additional_text_edits: None,
+    }]
+}
+
+/// Errors in the frontmatter and template syntax, located in the document.
+///
+/// `path` is the path of the template, which frontmatter partials are relative
+/// to.
+#[must_use]
+pub fn diagnostics(text: &str, path: Option<&Path>) -> Vec<Diagnostic> {
+    let problems =
+        validate::validate(text, path, &render::Options::default()).unwrap_or_else(|err| {
+            vec![validate::Problem {
+                line: frontmatter::line_offsets(text).1 + 1,
+                column: 1,
+                message: err.to_string(),
+            }]
+        });
+
+    problems
+        .into_iter()
+        .map(|problem| {
+            let line = problem.line - 1;
+            let line_text = text.lines().nth(line).unwrap_or_default();
+            let start = line_text
+                .char_indices()
+                .nth(problem.column - 1)
+                .map_or(line_text.len(), |(i, _)| i);
+            Diagnostic {
+                range: Range::new(
+                    Position::new(
+                        line as u32,
+                        line_text[..start].encode_utf16().count() as u32,
+                    ),
+                    Position::new(line as u32, line_text.encode_utf16().count() as u32),
+                ),
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("whiskers".to_string()),
+                message: problem.message,
+                ..Diagnostic::default()
+            }
+        })
+        .collect()
+}
+
+/// The byte offset of `position`, whose character counts UTF-16 code units.
+fn offset(text: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match text[line_start..].find('\n') {
+            Some(i) => line_start += i + 1,
+            None => return text.len(),
+        }
+    }
+    let line = text[line_start..].split('\n').next().unwrap_or_default();
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= position.character as usize {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    line_start + line.len()
+}
+
+/// The position of the byte `offset`.
+fn lsp_position(text: &str, offset: usize) -> Position {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Position::new(
+        before.matches('\n').count() as u32,
+        before[line_start..].encode_utf16().count() as u32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(items: &[CompletionItem]) -> Vec<&str> {
+        items.iter().map(|item| item.label.as_str()).collect()
+    }
+
+    #[test]
+    fn completes_variables_inside_tags() {
+        let text = "---\nwhiskers:\n  version: \"2\"\napp: helix\n---\n{{ r";
+        let items = completions(text, Position::new(5, 4));
+        let labels = labels(&items);
+        assert!(labels.contains(&"red"));
+        assert!(labels.contains(&"app"));
+        assert!(labels.contains(&"css_rgb"));
+        assert!(!labels.contains(&"whiskers"));
+
+        assert!(completions("red {{ red }} r", Position::new(0, 15)).is_empty());
+        assert!(completions(text, Position::new(3, 2)).is_empty());
+    }
+
+    #[test]
+    fn completes_fields_and_filters() {
+        let items = completions("{{ flavor.colors.", Position::new(0, 17));
+        let red = items
+            .iter()
+            .find(|item| item.label == "red")
+            .expect("red is a color of the flavor");
+        assert_eq!(red.kind, Some(CompletionItemKind::COLOR));
+        assert_eq!(
+            red.documentation,
+            Some(Documentation::String("#d20f39".to_string()))
+        );
+
+        let items = completions("{{ red | m", Position::new(0, 10));
+        let mix = items
+            .iter()
+            .find(|item| item.label == "mix")
+            .expect("mix is a filter");
+        assert_eq!(mix.detail.as_deref(), Some("mix(color, amount, space)"));
+    }
+
+    #[test]
+    fn completes_matrix_iterables() {
+        let text = "---\nwhiskers:\n  version: \"2\"\n  matrix:\n    - variant: [a, b]\n  filename: x\n---\n{{ ";
+        let items = completions(text, Position::new(7, 3));
+        let labels = labels(&items);
+        assert!(labels.contains(&"variant"));
+        assert!(labels.contains(&"matrix"));
+    }
+
+    #[test]
+    fn hover_shows_filter_docs() {
+        let text = "{{ red | mix(color=base) }}";
+        let hover = hover(text, Position::new(0, 10)).expect("mix is a filter");
+        let HoverContents::Markup(contents) = hover.contents else {
+            panic!("hover is markdown");
+        };
+        assert!(contents.value.contains("Mix two colors together"));
+        assert!(super::hover(text, Position::new(0, 4)).is_none());
+    }
+
+    #[test]
+    fn finds_hex_literals() {
+        let colors = document_colors("a: \"#ff0000\"\nb: #00ff0080 &#123456; #abc");
+        assert_eq!(colors.len(), 2);
+        assert_eq!(
+            colors[0].range,
+            Range::new(Position::new(0, 4), Position::new(0, 11))
+        );
+        assert_eq!(color_presentations(colors[0].color)[0].label, "#ff0000");
+        assert_eq!(colors[1].range.start, Position::new(1, 3));
+        assert!((colors[1].color.alpha - 0.5).abs() < 0.01);
+
+        let presentations = color_presentations(colors[1].color);
+        assert_eq!(presentations[0].label, "#00ff0080");
+    }
+
+    #[test]
+    fn locates_syntax_errors() {
+        let text = "---\nwhiskers:\n  version: \"2\"\n---\nline one\n{{ red. }}\n";
+        let diagnostics = diagnostics(text, None);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range.start.line, 5);
+        assert!(diagnostics[0].message.starts_with("Template is invalid"));
+    }
+
+    #[test]
+    fn locates_frontmatter_errors() {
+        let diagnostics = diagnostics("---\napp: [\n---\nbody\n", None);
+        assert_eq!(diagnostics[0].range.start.line, 2);
+
+        let text = "---\napp: helix\nwhiskers:\n  version: \"^1\"\n---\nbody\n";
+        let diagnostics = super::diagnostics(text, None);
+        assert_eq!(diagnostics[0].range.start.line, 3);
+        assert!(diagnostics[0]
+            .message
+            .contains("requires whiskers version ^1"));
+
+        assert!(super::diagnostics("{{ red.hex }}", None).is_empty());
+    }
+}
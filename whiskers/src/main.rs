@@ -1,209 +1,447 @@
 use std::{
-    collections::{hash_map::Entry, HashMap},
+    collections::HashSet,
     env,
-    io::{Read, Write as _},
+    ffi::OsStr,
+    io::{IsTerminal as _, Read, Write as _},
     path::{Path, PathBuf},
     process,
+    sync::mpsc,
+    time::Duration,
 };
 
 use anyhow::{anyhow, Context as _};
-use catppuccin::FlavorName;
-use clap::Parser as _;
+use clap::{CommandFactory as _, FromArgMatches as _};
 use encoding_rs_io::DecodeReaderBytes;
-use itertools::Itertools;
 use whiskers::{
-    cli::{Args, OutputFormat},
-    context::merge_values,
-    frontmatter, markdown,
-    matrix::{self, Matrix},
-    models, templating,
+    audit, check,
+    cli::{self, Args, AuditArgs, Command, OutputFormat, TestArgs, ValidateArgs},
+    config,
+    diagnostic::Diagnostic,
+    lsp, manifest, markdown, models,
+    render::{self, Output, RenderRequest, Renderer},
+    templating, testing, validate,
 };
 
-const FRONTMATTER_OPTIONS_SECTION: &str = "whiskers";
+fn main() -> anyhow::Result<()> {
+    // parse command-line arguments & template frontmatter
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    if matches!(args.command, Some(Command::Lsp)) {
+        return lsp::run().context("Language server failed");
+    }
+    let config = load_config(&args)?;
+    match args.command {
+        Some(Command::Audit(ref mut audit_args)) => {
+            config
+                .apply_audit(audit_args)
+                .context("Configuration could not be applied")?;
+            check_flavor(audit_args.flavor.as_deref(), audit_args.palette.as_ref());
+            return audit(audit_args);
+        }
+        Some(Command::Validate(ref validate_args)) => {
+            return validate(validate_args, config_options(config)?);
+        }
+        Some(Command::Test(ref test_args)) => return test(test_args, config_options(config)?),
+        Some(Command::Lsp) | None => {}
+    }
+    let defaults = config
+        .apply(&mut args)
+        .context("Configuration could not be applied")?;
+    check_flavor(args.flavor.as_deref(), args.palette.as_ref());
+    if args.template.is_empty() && args.batch.is_none() && !args.list_functions {
+        Args::command()
+            .bin_name("whiskers")
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "the following required arguments were not provided:\n  [TEMPLATE]...",
+            )
+            .exit();
+    }
+
+    if args.list_functions {
+        list_functions(args.output_format);
+        return Ok(());
+    }
+
+    if args.watch {
+        return watch(&args, &matches);
+    }
+
+    render(&args, &defaults)
+}
+
+/// The configuration file given with `--config`, or the closest one to the
+/// working directory unless `--no-config` is set.
+fn config_path(args: &Args) -> Option<PathBuf> {
+    if args.no_config {
+        return None;
+    }
+    args.config.clone().or_else(|| {
+        env::current_dir()
+            .ok()
+            .and_then(|dir| config::discover(&dir))
+    })
+}
+
+/// The configuration file, if there is one, or an empty configuration.
+fn load_config(args: &Args) -> anyhow::Result<config::Config> {
+    Ok(config_path(args)
+        .map(|path| config::Config::from_file(&path))
+        .transpose()?
+        .unwrap_or_default())
+}
+
+/// Fill in the arguments that weren't given on the command line from the
+/// configuration file, and return the render options that have no arguments.
+fn apply_config(args: &mut Args) -> anyhow::Result<render::Options> {
+    load_config(args)?
+        .apply(args)
+        .context("Configuration could not be applied")
+}
 
-#[derive(Default, Debug, serde::Deserialize)]
-struct TemplateOptions {
-    version: Option<semver::VersionReq>,
-    matrix: Option<Matrix>,
-    filename: Option<String>,
-    hex_prefix: Option<String>,
-    #[serde(default)]
-    capitalize_hex: bool,
+fn config_options(config: config::Config) -> anyhow::Result<render::Options> {
+    config
+        .render_options()
+        .context("Configuration could not be applied")
 }
 
-impl TemplateOptions {
-    fn from_frontmatter(
-        frontmatter: &HashMap<String, tera::Value>,
-        only_flavor: Option<FlavorName>,
-    ) -> anyhow::Result<Self> {
-        // a `TemplateOptions` object before matrix transformation
-        #[derive(serde::Deserialize)]
-        struct RawTemplateOptions {
-            version: Option<semver::VersionReq>,
-            matrix: Option<Vec<tera::Value>>,
-            filename: Option<String>,
-            hex_prefix: Option<String>,
-            #[serde(default)]
-            capitalize_hex: bool,
+/// Exit with a usage error if `flavor` is not in the palette. This can't be
+/// done by clap, as the flavors depend on the `--palette` argument.
+fn check_flavor(flavor: Option<&str>, custom: Option<&models::CustomPalette>) {
+    let Some(flavor) = flavor else {
+        return;
+    };
+    let flavors = models::flavor_identifiers(custom);
+    if !flavors.iter().any(|identifier| identifier == flavor) {
+        Args::command()
+            .bin_name("whiskers")
+            .error(
+                clap::error::ErrorKind::InvalidValue,
+                format!(
+                    "invalid value '{flavor}' for '--flavor <FLAVOR>'\n  [possible values: {}]",
+                    flavors.join(", ")
+                ),
+            )
+            .exit();
+    }
+}
+
+fn render(args: &Args, defaults: &render::Options) -> anyhow::Result<()> {
+    let templates = templates(args)?;
+    let batch = templates.len() > 1;
+    if batch && matches!(args.check, Some(Some(_))) {
+        anyhow::bail!("--check can't be given a file argument when rendering multiple templates");
+    }
+    if matches!(args.prune, Some(None)) && args.manifest.is_none() {
+        anyhow::bail!("--prune requires a glob or --manifest");
+    }
+
+    let mut renderer = Renderer::new(render::Options {
+        flavor: args.flavor.clone(),
+        palette: args.palette.clone(),
+        color_overrides: args.color_overrides.clone(),
+        overrides: args.overrides.clone(),
+        simulate: args.simulate,
+        partials: args.partials.clone(),
+        jobs: args.jobs,
+        ..defaults.clone()
+    });
+    let mut summary = check::Summary::default();
+    let mut files = vec![];
+    let mut failed = 0;
+    for template in &templates {
+        match render_template(template, args, &mut renderer, &mut summary) {
+            Ok(entries) => files.extend(entries.into_iter().flatten()),
+            Err(e) if batch => {
+                let path = match template.source {
+                    clap_stdin::Source::Stdin => "stdin",
+                    clap_stdin::Source::Arg(ref path) => path,
+                };
+                eprintln!("Error: {:?}", e.context(format!("Failed to render {path}")));
+                failed += 1;
+            }
+            Err(e) => return Err(e),
         }
+    }
 
-        if let Some(opts) = frontmatter.get(FRONTMATTER_OPTIONS_SECTION) {
-            let opts: RawTemplateOptions = tera::from_value(opts.clone())
-                .context("Frontmatter `whiskers` section is invalid")?;
-            let matrix = opts
-                .matrix
-                .map(|m| matrix::from_values(m, only_flavor))
-                .transpose()
-                .context("Frontmatter matrix is invalid")?;
-            Ok(Self {
-                version: opts.version,
-                matrix,
-                filename: opts.filename,
-                hex_prefix: opts.hex_prefix,
-                capitalize_hex: opts.capitalize_hex,
-            })
-        } else {
-            Ok(Self::default())
+    // outputs of templates that failed to render would look stale, so only
+    // prune and write the manifest when everything rendered.
+    if failed == 0 {
+        if args.prune.is_some() {
+            prune_stale_outputs(&files, args, &mut summary)
+                .context("Pruning stale outputs failed")?;
         }
+
+        // check mode only writes the outputs that --update replaces, so the
+        // manifest would list files that weren't written.
+        let wrote_outputs = args.check.is_none() || args.update;
+        if let Some(path) = args.manifest.as_ref().filter(|_| wrote_outputs) {
+            let manifest = manifest::Manifest {
+                dry_run: args.dry_run,
+                files,
+            };
+            manifest
+                .write(path)
+                .context("Manifest could not be written")?;
+        }
+    }
+
+    let passed = args.check.is_none() || finish_check(&summary, args)?;
+    if failed > 0 {
+        anyhow::bail!("{failed} of {} templates failed to render", templates.len());
+    }
+    if !passed {
+        process::exit(1);
     }
+
+    Ok(())
 }
 
-fn main() -> anyhow::Result<()> {
-    // parse command-line arguments & template frontmatter
-    let args = Args::parse();
+/// The templates given on the command line and in the `--batch` file, with
+/// globs expanded.
+fn templates(args: &Args) -> anyhow::Result<Vec<clap_stdin::FileOrStdin>> {
+    let mut patterns = args.template.clone();
+    if let Some(ref batch) = args.batch {
+        for pattern in &batch.templates {
+            patterns.push(pattern.parse()?);
+        }
+    }
 
-    if args.list_functions {
-        list_functions(args.output_format);
-        return Ok(());
+    let mut templates = vec![];
+    for template in patterns {
+        let clap_stdin::Source::Arg(ref pattern) = template.source else {
+            templates.push(template);
+            continue;
+        };
+        let is_glob = pattern.contains(['*', '?', '[']) && !Path::new(pattern).exists();
+        if !is_glob {
+            templates.push(template);
+            continue;
+        }
+        let paths = glob::glob(pattern)
+            .with_context(|| format!("Invalid glob {pattern}"))?
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("Couldn't read templates matching {pattern}"))?;
+        if paths.is_empty() {
+            anyhow::bail!("No templates match {pattern}");
+        }
+        for path in paths {
+            templates.push(path.display().to_string().parse()?);
+        }
     }
+    Ok(templates)
+}
 
-    let template = args
-        .template
+/// Render a single template, returning the manifest entries of the files it
+/// produced in multi-output mode.
+fn render_template(
+    template: &clap_stdin::FileOrStdin,
+    args: &Args,
+    renderer: &mut Renderer,
+    summary: &mut check::Summary,
+) -> anyhow::Result<Option<Vec<manifest::Entry>>> {
+    let path = match template.source {
+        clap_stdin::Source::Stdin => None,
+        clap_stdin::Source::Arg(ref path) => Some(PathBuf::from(path)),
+    };
+    let display_name = path
         .as_ref()
-        .expect("args.template is guaranteed by clap to be set");
-    let template_from_stdin = matches!(template.source, clap_stdin::Source::Stdin);
-    let template_name = template_name(template);
+        .map_or_else(|| "<stdin>".to_string(), |path| path.display().to_string());
+    let request = RenderRequest {
+        source: read_template(template)?,
+        name: template_name(template),
+        check_version: path.is_some(),
+        path,
+    };
+    let result = renderer
+        .render(&request)
+        .map_err(|e| Diagnostic::new(&e, &request.source, &display_name))?;
+    if result.missing_version {
+        warn_missing_version();
+    }
+
+    match result.output {
+        Output::Single(result) => {
+            if args.manifest.is_some() {
+                anyhow::bail!("--manifest requires a template with a matrix");
+            }
+            if args.prune.is_some() {
+                anyhow::bail!("--prune requires a template with a matrix");
+            }
+            let check = args
+                .check
+                .clone()
+                .map(|c| {
+                    c.ok_or_else(|| {
+                        anyhow!("--check requires a file argument in single-output mode")
+                    })
+                })
+                .transpose()?;
+            write_single_output(&result, check, args.update, summary)?;
+            Ok(None)
+        }
+        Output::Multi(files) => write_multi_output(files, args, summary).map(Some),
+    }
+}
 
+fn read_template(template: &clap_stdin::FileOrStdin) -> anyhow::Result<String> {
     let mut decoder = DecodeReaderBytes::new(
         template
             .into_reader()
             .context("Failed to open template file")?,
     );
-    let mut template = String::new();
+    let mut contents = String::new();
     decoder
-        .read_to_string(&mut template)
+        .read_to_string(&mut contents)
         .context("Template could not be read")?;
+    Ok(contents)
+}
 
-    let doc = frontmatter::parse(&template).context("Frontmatter is invalid")?;
-    let mut template_opts =
-        TemplateOptions::from_frontmatter(&doc.frontmatter, args.flavor.map(Into::into))
-            .context("Could not get template options from frontmatter")?;
-
-    if !template_from_stdin && !template_is_compatible(&template_opts) {
-        std::process::exit(1);
-    }
-
-    // merge frontmatter with command-line overrides and add to Tera context
-    let mut frontmatter = doc.frontmatter;
-    if let Some(ref overrides) = args.overrides {
-        for (key, value) in overrides {
-            frontmatter
-                .entry(key.clone())
-                .and_modify(|v| {
-                    *v = merge_values(v, value);
-                })
-                .or_insert(
-                    tera::to_value(value)
-                        .with_context(|| format!("Value of {key} override is invalid"))?,
-                );
-
-            // overrides also work on matrix iterables
-            if let Some(ref mut matrix) = template_opts.matrix {
-                override_matrix(matrix, value, key)?;
-            }
-        }
-    }
-    let mut ctx = tera::Context::new();
-    for (key, value) in &frontmatter {
-        ctx.insert(key, &value);
-    }
+/// Re-render the template whenever it, the partials, or any of the override
+/// files change.
+fn watch(args: &Args, matches: &clap::ArgMatches) -> anyhow::Result<()> {
+    let [template] = args.template.as_slice() else {
+        anyhow::bail!("--watch requires a single template");
+    };
+    let clap_stdin::Source::Arg(ref template_path) = template.source else {
+        anyhow::bail!("--watch requires a template file, not stdin");
+    };
 
-    // build the palette and add it to the templating context
-    let palette = models::build_palette(
-        template_opts.capitalize_hex,
-        template_opts.hex_prefix.as_deref(),
-        args.color_overrides.as_ref(),
-    )
-    .context("Palette context cannot be built")?;
+    let template_path = Path::new(template_path);
 
-    ctx.insert("flavors", &palette.flavors);
-    if let Some(flavor) = args.flavor {
-        let flavor: catppuccin::FlavorName = flavor.into();
-        let flavor = &palette.flavors[flavor.identifier()];
-        ctx.insert("flavor", flavor);
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).context("Failed to create file watcher")?;
+    let mut watched_dirs = HashSet::new();
+    let mut paths = watched_paths(template_path, args, matches)?;
 
-        // also throw in the flavor's colors for convenience
-        for (_, color) in flavor {
-            ctx.insert(&color.identifier, &color);
+    let mut args = Args::from_arg_matches(matches)?;
+    let mut defaults = apply_config(&mut args)?;
+    loop {
+        watch_paths(&mut watcher, &mut watched_dirs, &paths)?;
+        if let Err(e) = render(&args, &defaults) {
+            eprintln!("Error: {e:?}");
+        }
+        eprintln!("Watching for changes...");
+
+        wait_for_change(&rx, &paths)?;
+
+        // parse the arguments again so that override files are re-read.
+        match Args::command()
+            .try_get_matches()
+            .and_then(|matches| Args::from_arg_matches(&matches))
+        {
+            Ok(mut new_args) => match apply_config(&mut new_args) {
+                Ok(new_defaults) => (args, defaults) = (new_args, new_defaults),
+                Err(e) => eprintln!("Error: {e:?}"),
+            },
+            Err(e) => eprintln!("{e}"),
+        }
+        // the template's frontmatter can change its partials directory.
+        match watched_paths(template_path, &args, matches) {
+            Ok(new_paths) => paths = new_paths,
+            Err(e) => eprintln!("Error: {e:?}"),
         }
     }
+}
 
-    // build the Tera engine
-    let mut tera = templating::make_engine();
-    tera.add_raw_template(&template_name, &doc.body)
-        .context("Template is invalid")?;
+/// The files and directories that `--watch` re-renders on changes to: the
+/// template, the input files, the configuration file and the partials.
+fn watched_paths(
+    template: &Path,
+    args: &Args,
+    matches: &clap::ArgMatches,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let mut paths = vec![template.to_path_buf()];
+    paths.extend(cli::input_files(matches));
+    paths.extend(config_path(args));
+    paths.extend(args.partials.clone());
+    let mut paths = paths
+        .iter()
+        .map(|path| {
+            std::fs::canonicalize(path)
+                .with_context(|| format!("Couldn't resolve {}", path.display()))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    // a missing frontmatter partials directory is reported by the render.
+    let partials = std::fs::read_to_string(template)
+        .ok()
+        .and_then(|source| {
+            RenderRequest {
+                path: Some(template.to_path_buf()),
+                ..RenderRequest::new(source)
+            }
+            .frontmatter_partials()
+        })
+        .and_then(|partials| std::fs::canonicalize(partials).ok());
+    paths.extend(partials);
+    Ok(paths)
+}
 
-    if let Some(matrix) = template_opts.matrix {
-        let Some(filename_template) = template_opts.filename else {
-            anyhow::bail!("Filename template is required for multi-output render");
+/// Watch the directories of `paths` that aren't in `watched_dirs` yet.
+///
+/// Editors often save by replacing the file, so we watch the parent
+/// directories and filter the events by path instead of watching the files.
+/// Directories among the paths are watched recursively.
+fn watch_paths(
+    watcher: &mut impl notify::Watcher,
+    watched_dirs: &mut HashSet<(PathBuf, bool)>,
+    paths: &[PathBuf],
+) -> anyhow::Result<()> {
+    let parents = paths
+        .iter()
+        .filter_map(|path| path.parent())
+        .map(|dir| (dir.to_path_buf(), false));
+    let dirs = paths
+        .iter()
+        .filter(|path| path.is_dir())
+        .map(|dir| (dir.clone(), true));
+    for (dir, recursive) in parents.chain(dirs) {
+        if !watched_dirs.insert((dir.clone(), recursive)) {
+            continue;
+        }
+        let mode = if recursive {
+            notify::RecursiveMode::Recursive
+        } else {
+            notify::RecursiveMode::NonRecursive
         };
-        render_multi_output(
-            matrix,
-            &filename_template,
-            &ctx,
-            &palette,
-            &tera,
-            &template_name,
-            &args,
-        )
-        .context("Multi-output render failed")?;
-    } else {
-        let check = args
-            .check
-            .map(|c| {
-                c.ok_or_else(|| anyhow!("--check requires a file argument in single-output mode"))
-            })
-            .transpose()?;
-        render_single_output(&ctx, &tera, &template_name, check)
-            .context("Single-output render failed")?;
+        watcher
+            .watch(&dir, mode)
+            .with_context(|| format!("Failed to watch {}", dir.display()))?;
     }
-
     Ok(())
 }
 
-fn override_matrix(
-    matrix: &mut Matrix,
-    value: &tera::Value,
-    key: &str,
-) -> Result<(), anyhow::Error> {
-    let Entry::Occupied(e) = matrix.entry(key.to_string()) else {
-        return Ok(());
+/// Block until a watcher event touches one of `paths`, then swallow any
+/// further events that arrive in quick succession.
+fn wait_for_change(
+    rx: &mpsc::Receiver<notify::Result<notify::Event>>,
+    paths: &[PathBuf],
+) -> anyhow::Result<()> {
+    const DEBOUNCE: Duration = Duration::from_millis(100);
+
+    let is_relevant = |event: notify::Result<notify::Event>| match event {
+        Ok(event) => {
+            !event.kind.is_access()
+                && event
+                    .paths
+                    .iter()
+                    .any(|p| paths.iter().any(|path| p.starts_with(path)))
+        }
+        Err(e) => {
+            eprintln!("warning: File watcher error: {e}");
+            false
+        }
     };
 
-    // if the override is a list, we can just replace the iterable.
-    if let Some(value_list) = value.as_array() {
-        let value_list = value_list
-            .iter()
-            .map(|v| v.as_str().map(ToString::to_string))
-            .collect::<Option<Vec<_>>>()
-            .context("Override value is not a list of strings")?;
-        *e.into_mut() = value_list;
-    }
-    // if the override is a string, we instead replace the iterable with a
-    // single-element list containing the string.
-    else if let Some(value_string) = value.as_str() {
-        *e.into_mut() = vec![value_string.to_string()];
+    loop {
+        let event = rx.recv().context("File watcher stopped unexpectedly")?;
+        if is_relevant(event) {
+            break;
+        }
     }
+    while rx.recv_timeout(DEBOUNCE).is_ok() {}
 
     Ok(())
 }
@@ -240,6 +478,138 @@ fn list_functions(format: OutputFormat) {
     }
 }
 
+fn audit(args: &AuditArgs) -> anyhow::Result<()> {
+    let mut palette = models::build_palette(
+        false,
+        None,
+        args.color_overrides.as_ref(),
+        args.palette.as_ref(),
+    )
+    .context("Palette context cannot be built")?;
+    if let Some(ref flavor) = args.flavor {
+        palette.flavors.retain(|identifier, _| identifier == flavor);
+    }
+
+    let report = audit::audit(
+        &palette,
+        &audit::Options {
+            algorithm: args.algorithm,
+            threshold: args
+                .threshold
+                .unwrap_or_else(|| args.algorithm.default_threshold()),
+            foregrounds: &args.foreground,
+            backgrounds: &args.background,
+            distinguish: &args.distinguish,
+            min_distance: args.min_distance,
+            simulation: args.simulate,
+        },
+    )
+    .context("Contrast audit failed")?;
+
+    match args.output_format {
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&report).expect("report is guaranteed to be valid")
+        ),
+        OutputFormat::Yaml => print!(
+            "{}",
+            serde_yaml::to_string(&report).expect("report is guaranteed to be valid")
+        ),
+        OutputFormat::Markdown => print!("{}", report.to_markdown(markdown::Format::List)),
+        OutputFormat::MarkdownTable => {
+            print!("{}", report.to_markdown(markdown::Format::Table));
+        }
+    }
+
+    if !report.passed() {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+fn validate(args: &ValidateArgs, options: render::Options) -> anyhow::Result<()> {
+    let options = render::Options {
+        partials: args.partials.clone().or(options.partials),
+        ..options
+    };
+    let mut count = 0;
+    for template in &args.templates {
+        let (name, path) = match template.source {
+            clap_stdin::Source::Stdin => ("<stdin>", None),
+            clap_stdin::Source::Arg(ref path) => (path.as_str(), Some(Path::new(path))),
+        };
+        let source = read_template(template)?;
+        let problems = validate::validate(&source, path, &options)
+            .with_context(|| format!("{name} could not be validated"))?;
+        for problem in &problems {
+            eprintln!(
+                "{name}:{}:{}: {}",
+                problem.line, problem.column, problem.message
+            );
+        }
+        count += problems.len();
+    }
+
+    if count > 0 {
+        eprintln!(
+            "Validation failed: {count} problem{}",
+            if count == 1 { "" } else { "s" }
+        );
+        process::exit(1);
+    }
+    Ok(())
+}
+
+fn test(args: &TestArgs, options: render::Options) -> anyhow::Result<()> {
+    let options = render::Options {
+        partials: args.partials.clone().or(options.partials),
+        ..options
+    };
+    let (mut passed, mut failed) = (0, 0);
+    for template in &args.templates {
+        let path = match template.source {
+            clap_stdin::Source::Stdin => None,
+            clap_stdin::Source::Arg(ref path) => Some(PathBuf::from(path)),
+        };
+        let display_name = path
+            .as_ref()
+            .map_or_else(|| "<stdin>".to_string(), |path| path.display().to_string());
+        let request = RenderRequest {
+            source: read_template(template)?,
+            name: template_name(template),
+            check_version: path.is_some(),
+            path,
+        };
+        let outcomes = testing::run(&request, &options)
+            .with_context(|| format!("Failed to run the tests of {display_name}"))?;
+        if outcomes.is_empty() {
+            eprintln!("Warning: {display_name} declares no tests");
+        }
+
+        for outcome in outcomes {
+            if outcome.passed() {
+                println!("test {display_name}: {} ... ok", outcome.name);
+                passed += 1;
+            } else {
+                println!("test {display_name}: {} ... FAILED", outcome.name);
+                for failure in &outcome.failures {
+                    for line in failure.lines() {
+                        println!("    {line}");
+                    }
+                }
+                failed += 1;
+            }
+        }
+    }
+
+    let result = if failed == 0 { "ok" } else { "FAILED" };
+    println!("\ntest result: {result}. {passed} passed; {failed} failed");
+    if failed > 0 {
+        process::exit(1);
+    }
+    Ok(())
+}
+
 fn template_name(template: &clap_stdin::FileOrStdin) -> String {
     match &template.source {
         clap_stdin::Source::Stdin => "template".to_string(),
@@ -250,103 +620,129 @@ fn template_name(template: &clap_stdin::FileOrStdin) -> String {
     }
 }
 
-fn template_is_compatible(template_opts: &TemplateOptions) -> bool {
-    let whiskers_version = semver::Version::parse(env!("CARGO_PKG_VERSION"))
-        .expect("CARGO_PKG_VERSION is always valid");
-    if let Some(template_version) = &template_opts.version {
-        if !template_version.matches(&whiskers_version) {
-            eprintln!("Template requires whiskers version {template_version}, but you are running whiskers {whiskers_version}");
-            return false;
-        }
-    } else {
-        eprintln!("Warning: No Whiskers version requirement specified in template.");
-        eprintln!("This template may not be compatible with this version of Whiskers.");
-        eprintln!();
-        eprintln!("To fix this, add the minimum supported Whiskers version to the template frontmatter as follows:");
-        eprintln!();
-        eprintln!("---");
-        eprintln!("whiskers:");
-        eprintln!("    version: \"{whiskers_version}\"");
-        eprintln!("---");
-        eprintln!();
-    };
-
-    true
+fn warn_missing_version() {
+    let whiskers_version = env!("CARGO_PKG_VERSION");
+    eprintln!("Warning: No Whiskers version requirement specified in template.");
+    eprintln!("This template may not be compatible with this version of Whiskers.");
+    eprintln!();
+    eprintln!("To fix this, add the minimum supported Whiskers version to the template frontmatter as follows:");
+    eprintln!();
+    eprintln!("---");
+    eprintln!("whiskers:");
+    eprintln!("    version: \"{whiskers_version}\"");
+    eprintln!("---");
+    eprintln!();
 }
 
-fn render_single_output(
-    ctx: &tera::Context,
-    tera: &tera::Tera,
-    template_name: &str,
+fn write_single_output(
+    result: &[u8],
     check: Option<PathBuf>,
+    update: bool,
+    summary: &mut check::Summary,
 ) -> Result<(), anyhow::Error> {
-    let result = tera
-        .render(template_name, ctx)
-        .context("Template render failed")?;
-
     if let Some(path) = check {
-        check_result_with_file(&path, &result).context("Check mode failed")?;
+        check_result_with_file(&path, result, update, summary).context("Check mode failed")?;
     } else {
-        print!("{result}");
+        std::io::stdout()
+            .write_all(result)
+            .context("Couldn't write to stdout")?;
     }
 
     Ok(())
 }
 
-fn render_multi_output(
-    matrix: HashMap<String, Vec<String>>,
-    filename_template: &str,
-    ctx: &tera::Context,
-    palette: &models::Palette,
-    tera: &tera::Tera,
-    template_name: &str,
+fn write_multi_output(
+    files: Vec<render::File>,
     args: &Args,
-) -> Result<(), anyhow::Error> {
-    let iterables = matrix
-        .into_iter()
-        .map(|(key, iterable)| iterable.into_iter().map(move |v| (key.clone(), v)))
-        .multi_cartesian_product()
-        .collect::<Vec<_>>();
-
-    for iterable in iterables {
-        let mut ctx = ctx.clone();
-        for (key, value) in iterable {
-            // expand flavor automatically to prevent requiring:
-            // `{% set flavor = flavors[flavor] %}`
-            // at the top of every template.
-            if key == "flavor" {
-                let flavor: catppuccin::FlavorName = value.parse()?;
-                let flavor = &palette.flavors[flavor.identifier()];
-                ctx.insert("flavor", flavor);
-            } else {
-                ctx.insert(key, &value);
-            }
-        }
-        let result = tera
-            .render(template_name, &ctx)
-            .context("Main template render failed")?;
-        let filename = tera::Tera::one_off(filename_template, &ctx, false)
-            .context("Filename template render failed")?;
-        let filename = Path::new(&filename);
-
+    summary: &mut check::Summary,
+) -> Result<Vec<manifest::Entry>, anyhow::Error> {
+    let mut entries = vec![];
+    for file in files {
         if args.dry_run || cfg!(test) {
             println!(
                 "Would write {} bytes into {}",
-                result.as_bytes().len(),
-                filename.display()
+                file.contents.len(),
+                file.filename.display()
             );
         } else if args.check.is_some() {
-            check_result_with_file(&filename, &result).context("Check mode failed")?;
+            check_result_with_file(&file.filename, &file.contents, args.update, summary)
+                .context("Check mode failed")?;
         } else {
-            maybe_create_parents(filename)?;
-            std::fs::write(filename, result)
-                .with_context(|| format!("Couldn't write to {}", filename.display()))?;
+            maybe_create_parents(&file.filename)?;
+            std::fs::write(&file.filename, &file.contents)
+                .with_context(|| format!("Couldn't write to {}", file.filename.display()))?;
         }
+        entries.push(manifest::Entry::new(
+            file.combination.into_iter().collect(),
+            file.filename,
+            &file.contents,
+        ));
     }
 
+    Ok(entries)
+}
+
+/// Delete the previously generated files that are no longer in `files`, or
+/// report them in check mode.
+///
+/// Previously generated files are found with the `--prune` glob, or in the
+/// existing `--manifest` when no glob is given.
+fn prune_stale_outputs(
+    files: &[manifest::Entry],
+    args: &Args,
+    summary: &mut check::Summary,
+) -> anyhow::Result<()> {
+    let previous = match (args.prune.as_ref().and_then(Option::as_ref), &args.manifest) {
+        (Some(pattern), _) => glob::glob(pattern)
+            .with_context(|| format!("Invalid glob {pattern}"))?
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("Couldn't read files matching {pattern}"))?,
+        (None, Some(path)) if path.exists() => manifest::Manifest::read(path)
+            .context("Previous manifest could not be read")?
+            .files
+            .into_iter()
+            .map(|entry| entry.filename)
+            .collect(),
+        (None, Some(_)) => vec![],
+        (None, None) => unreachable!("--prune without a glob is checked to have --manifest"),
+    };
+
+    // the manifest may match the glob, but it's never a stale output.
+    let keep = files
+        .iter()
+        .map(|entry| normalize_path(&entry.filename))
+        .chain(args.manifest.as_deref().map(normalize_path))
+        .collect::<HashSet<_>>();
+    let stale = previous
+        .into_iter()
+        .filter(|path| path.is_file() && !keep.contains(&normalize_path(path)))
+        .collect::<Vec<_>>();
+
+    for path in &stale {
+        if args.dry_run || cfg!(test) {
+            println!("Would delete {}", path.display());
+        } else if args.check.is_some() && !args.update {
+            eprintln!("{} is no longer generated", path.display());
+            summary.record(path, check::Status::Stale);
+        } else {
+            std::fs::remove_file(path)
+                .with_context(|| format!("Couldn't delete {}", path.display()))?;
+            if args.update {
+                eprintln!("Removed {}", path.display());
+                summary.record(path, check::Status::Removed);
+            }
+        }
+    }
     Ok(())
 }
 
+/// Strip `.` components so that `./a.txt` and `a.txt` compare equal.
+fn normalize_path(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| *component != std::path::Component::CurDir)
+        .collect()
+}
+
 fn maybe_create_parents(filename: &Path) -> anyhow::Result<()> {
     if let Some(parent) = filename.parent() {
         std::fs::create_dir_all(parent).with_context(|| {
@@ -355,45 +751,117 @@ fn maybe_create_parents(filename: &Path) -> anyhow::Result<()> {
                 filename.display()
             )
         })?;
-    };
+    }
     Ok(())
 }
 
-fn check_result_with_file<P>(path: &P, result: &str) -> anyhow::Result<()>
-where
-    P: AsRef<Path>,
-{
-    let path = path.as_ref();
-    let expected = std::fs::read_to_string(path).with_context(|| {
-        format!(
-            "Couldn't read {} for comparison against result",
-            path.display()
-        )
-    })?;
-    if *result != expected {
-        eprintln!("Output does not match {}", path.display());
-        invoke_difftool(result, path)?;
-        std::process::exit(1);
+/// Compare `result` against the file at `path`, recording the outcome in
+/// `summary` and showing a diff if they differ. In update mode, the file is
+/// written instead if it differs or is missing.
+fn check_result_with_file(
+    path: &Path,
+    result: &[u8],
+    update: bool,
+    summary: &mut check::Summary,
+) -> anyhow::Result<()> {
+    let expected = match std::fs::read(path) {
+        Ok(expected) => Some(expected),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => {
+            return Err(e).with_context(|| {
+                format!(
+                    "Couldn't read {} for comparison against result",
+                    path.display()
+                )
+            })
+        }
+    };
+    if expected.as_deref() == Some(result) {
+        summary.record(path, check::Status::Match);
+        return Ok(());
+    }
+
+    if update {
+        maybe_create_parents(path)?;
+        std::fs::write(path, result)
+            .with_context(|| format!("Couldn't write to {}", path.display()))?;
+        let status = if expected.is_some() {
+            eprintln!("Updated {}", path.display());
+            check::Status::Updated
+        } else {
+            eprintln!("Created {}", path.display());
+            check::Status::Created
+        };
+        summary.record(path, status);
+        return Ok(());
+    }
+
+    let Some(expected) = expected else {
+        eprintln!("{} is missing", path.display());
+        summary.record(path, check::Status::Missing);
+        return Ok(());
+    };
+    eprintln!("Output does not match {}", path.display());
+    summary.record(path, check::Status::Mismatch);
+    if let Some(tool) = env::var_os("DIFFTOOL") {
+        return invoke_difftool(&tool, result, path);
     }
+    let color = std::io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none();
+    eprint!("{}", check::unified_diff(&expected, result, path, color));
     Ok(())
 }
 
-fn invoke_difftool<P>(actual: &str, expected_path: P) -> anyhow::Result<()>
-where
-    P: AsRef<Path>,
-{
-    let expected_path = expected_path.as_ref();
-    let tool = env::var("DIFFTOOL").unwrap_or_else(|_| "diff".to_string());
+/// Write the `--check-summary`, returning whether every file matched.
+fn finish_check(summary: &check::Summary, args: &Args) -> anyhow::Result<bool> {
+    if let Some(ref path) = args.check_summary {
+        let json = serde_json::to_string_pretty(summary)? + "\n";
+        if path.as_os_str() == "-" {
+            print!("{json}");
+        } else {
+            std::fs::write(path, json)
+                .with_context(|| format!("Couldn't write check summary to {}", path.display()))?;
+        }
+    }
+
+    if !summary.passed {
+        eprintln!(
+            "Check failed: {} mismatched, {} missing, {} stale",
+            summary.count(check::Status::Mismatch),
+            summary.count(check::Status::Missing),
+            summary.count(check::Status::Stale),
+        );
+    } else if args.update {
+        let changed = [
+            check::Status::Updated,
+            check::Status::Created,
+            check::Status::Removed,
+        ]
+        .map(|status| summary.count(status));
+        if changed.iter().sum::<usize>() == 0 {
+            eprintln!("All files are up to date");
+        } else {
+            eprintln!(
+                "Update finished: {} updated, {} created, {} removed",
+                changed[0], changed[1], changed[2],
+            );
+        }
+    }
+    Ok(summary.passed)
+}
 
+fn invoke_difftool(tool: &OsStr, actual: &[u8], expected_path: &Path) -> anyhow::Result<()> {
     let mut actual_file = tempfile::NamedTempFile::new()?;
-    write!(&mut actual_file, "{actual}")?;
+    actual_file.write_all(actual)?;
     if let Ok(mut child) = process::Command::new(tool)
         .args([actual_file.path(), expected_path])
         .spawn()
     {
         child.wait()?;
     } else {
-        eprintln!("warning: Can't display diff, try setting $DIFFTOOL.");
+        eprintln!(
+            "warning: Can't display diff, failed to run $DIFFTOOL ({}).",
+            tool.to_string_lossy()
+        );
     }
 
     Ok(())
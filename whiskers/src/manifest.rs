@@ -0,0 +1,100 @@
+//! a machine-readable record of the files produced by a multi-output render.
+
+use std::path::{Path, PathBuf};
+
+use indexmap::IndexMap;
+use sha2::{Digest as _, Sha256};
+
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Manifest {
+    /// Whether the files were only rendered and not written.
+    pub dry_run: bool,
+    pub files: Vec<Entry>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Entry {
+    /// The matrix combination the file was rendered for.
+    pub matrix: IndexMap<String, String>,
+    pub filename: PathBuf,
+    /// Size of the encoded output in bytes.
+    pub size: usize,
+    /// SHA-256 of the encoded output, as lowercase hex.
+    pub sha256: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to serialize manifest: {message}")]
+    Serialize { message: String },
+
+    #[error("Failed to read manifest from {path}")]
+    Read {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Failed to parse manifest {path}: {message}")]
+    Parse { path: String, message: String },
+
+    #[error("Failed to write manifest to {path}")]
+    Write {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+impl Entry {
+    #[must_use]
+    pub fn new(matrix: IndexMap<String, String>, filename: PathBuf, contents: &[u8]) -> Self {
+        Self {
+            matrix,
+            filename,
+            size: contents.len(),
+            sha256: format!("{:x}", Sha256::digest(contents)),
+        }
+    }
+}
+
+impl Manifest {
+    /// Read a manifest written by [`Manifest::write`].
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let contents = std::fs::read_to_string(path).map_err(|e| Error::Read {
+            path: path.display().to_string(),
+            source: e,
+        })?;
+        if is_yaml(path) {
+            serde_yaml::from_str(&contents).map_err(|e| e.to_string())
+        } else {
+            serde_json::from_str(&contents).map_err(|e| e.to_string())
+        }
+        .map_err(|message| Error::Parse {
+            path: path.display().to_string(),
+            message,
+        })
+    }
+
+    /// Write the manifest as YAML if `path` has a `.yaml` or `.yml`
+    /// extension, or as JSON otherwise.
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let contents = if is_yaml(path) {
+            serde_yaml::to_string(self).map_err(|e| e.to_string())
+        } else {
+            serde_json::to_string_pretty(self)
+                .map(|json| json + "\n")
+                .map_err(|e| e.to_string())
+        }
+        .map_err(|message| Error::Serialize { message })?;
+        std::fs::write(path, contents).map_err(|e| Error::Write {
+            path: path.display().to_string(),
+            source: e,
+        })
+    }
+}
+
+fn is_yaml(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == "yaml" || ext == "yml")
+}
@@ -1,3 +1,5 @@
+use std::fmt::Write as _;
+
 use itertools::Itertools as _;
 
 use crate::templating;
@@ -20,16 +22,18 @@ fn list_format() -> String {
     let mut result = String::new();
     result.push_str("## Functions\n\n");
     for function in templating::all_functions() {
-        result.push_str(&format!(
+        let _ = write!(
+            result,
             "### `{name}`\n\n{description}\n\n",
             name = function.name,
             description = function.description
-        ));
+        );
         if !function.examples.is_empty() {
             result.push_str("#### Examples\n\n");
             for example in &function.examples {
-                result.push_str(&format!(
-                    "- `{name}({input})` => `{output}`\n",
+                let _ = writeln!(
+                    result,
+                    "- `{name}({input})` => `{output}`",
                     name = function.name,
                     input = example
                         .inputs
@@ -37,7 +41,7 @@ fn list_format() -> String {
                         .map(|(k, v)| format!("{k}={v}"))
                         .join(", "),
                     output = example.output
-                ));
+                );
             }
             result.push('\n');
         }
@@ -45,16 +49,18 @@ fn list_format() -> String {
 
     result.push_str("## Filters\n\n");
     for filter in templating::all_filters() {
-        result.push_str(&format!(
+        let _ = write!(
+            result,
             "### `{name}`\n\n{description}\n\n",
             name = filter.name,
             description = filter.description
-        ));
+        );
         if !filter.examples.is_empty() {
             result.push_str("#### Examples\n\n");
             for example in &filter.examples {
-                result.push_str(&format!(
-                    "- `{value} | {name}({input})` => `{output}`\n",
+                let _ = writeln!(
+                    result,
+                    "- `{value} | {name}({input})` => `{output}`",
                     value = example.value,
                     name = filter.name,
                     input = example
@@ -63,7 +69,7 @@ fn list_format() -> String {
                         .map(|(k, v)| format!("{k}={v}"))
                         .join(", "),
                     output = example.output
-                ));
+                );
             }
             result.push('\n');
         }
@@ -78,8 +84,9 @@ fn table_format() -> String {
     result.push_str("| Name | Description | Examples |\n");
     result.push_str("|------|-------------|----------|\n");
     for function in templating::all_functions() {
-        result.push_str(&format!(
-            "| `{name}` | {description} | {examples} |\n",
+        let _ = writeln!(
+            result,
+            "| `{name}` | {description} | {examples} |",
             name = function.name,
             description = function.description,
             examples = if function.examples.is_empty() {
@@ -101,15 +108,16 @@ fn table_format() -> String {
                         )
                     })
             }
-        ));
+        );
     }
 
     result.push_str("### Filters\n\n");
     result.push_str("| Name | Description | Examples |\n");
     result.push_str("|------|-------------|----------|\n");
     for filter in templating::all_filters() {
-        result.push_str(&format!(
-            "| `{name}` | {description} | {examples} |\n",
+        let _ = writeln!(
+            result,
+            "| `{name}` | {description} | {examples} |",
             name = filter.name,
             description = filter.description,
             examples = if filter.examples.is_empty() {
@@ -129,7 +137,7 @@ fn table_format() -> String {
                     )
                 })
             }
-        ));
+        );
     }
 
     result
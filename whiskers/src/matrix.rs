@@ -1,8 +1,25 @@
 use std::collections::HashMap;
 
-use catppuccin::FlavorName;
+use indexmap::IndexMap;
+use itertools::Itertools as _;
 
-pub type Matrix = HashMap<String, Vec<String>>;
+#[derive(Clone, Debug, Default)]
+pub struct Matrix {
+    /// The iterables, in the order they are declared in.
+    pub iterables: IndexMap<String, Vec<String>>,
+    /// Partial combinations to leave out of the cartesian product.
+    pub exclude: Vec<IndexMap<String, String>>,
+    /// Values to add to the combinations that match the entry's values of the
+    /// iterables, or extra combinations if none match.
+    pub include: Vec<IndexMap<String, String>>,
+}
+
+/// A single combination of matrix values, as `(key, value)` pairs.
+pub type Combination = Vec<(String, String)>;
+
+/// Name of the context variable that describes the combination being
+/// rendered, which matrix iterables and frontmatter keys can't use.
+pub const RESERVED_KEY: &str = "matrix";
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -14,52 +31,214 @@ pub enum Error {
 
     #[error("Invalid matrix array element: must be a string or object")]
     InvalidElement,
+
+    #[error("Invalid matrix {rule} element: must be an array of objects with string values")]
+    InvalidRule { rule: &'static str },
+
+    #[error("Matrix {rule} entries must not be empty")]
+    EmptyRule { rule: &'static str },
+
+    #[error("Matrix {rule} entry refers to {key}, which is not in the matrix")]
+    UnknownRuleKey { rule: &'static str, key: String },
+
+    #[error("Matrix include entry matches no combination, and can't be a combination of its own without a value for {key}")]
+    IncompleteInclude { key: String },
+
+    #[error("Matrix include entry refers to flavor {flavor}, which is not in the palette")]
+    UnknownIncludeFlavor { flavor: String },
+
+    #[error("Matrix can't set `matrix`, which is reserved for the matrix context variable")]
+    ReservedKey,
 }
 
 // matrix in frontmatter is a list of strings or objects.
 // objects must have a single key and an array of strings as the value.
 // string array elements are substituted with the array from `iterables`.
-pub fn from_values(
-    values: Vec<tera::Value>,
-    only_flavor: Option<FlavorName>,
-) -> Result<Matrix, Error> {
-    let iterables = magic_iterables(only_flavor);
-    values
-        .into_iter()
-        .map(|v| match v {
+// `flavors` are the identifiers of the flavors in the palette, which the
+// `flavor` magic iterable expands to. narrowing it to fewer flavors afterwards,
+// as `--flavor` does, also drops their include entries.
+//
+// `exclude` objects hold an array of combinations to remove from the cartesian
+// product, and `include` objects an array of values to add to it, like in
+// GitHub Actions.
+pub fn from_values(values: Vec<tera::Value>, flavors: Vec<String>) -> Result<Matrix, Error> {
+    let magic = magic_iterables(flavors);
+    let mut matrix = Matrix::default();
+    for value in values {
+        match value {
             tera::Value::String(s) => {
-                let iterable = iterables
+                let iterable = magic
                     .get(s.as_str())
-                    .ok_or(Error::UnknownIterable { name: s.clone() })?;
-                Ok((s, iterable.clone()))
+                    .ok_or_else(|| Error::UnknownIterable { name: s.clone() })?;
+                matrix.iterables.insert(s, iterable.clone());
             }
             tera::Value::Object(o) => {
                 let (key, value) = o.into_iter().next().ok_or(Error::InvalidObjectElement)?;
-                let value: Vec<String> =
-                    tera::from_value(value).map_err(|_| Error::InvalidObjectElement)?;
-                Ok((key, value))
+                match key.as_str() {
+                    "exclude" => matrix.exclude.extend(rules(value, "exclude")?),
+                    "include" => matrix.include.extend(rules(value, "include")?),
+                    _ => {
+                        let value: Vec<String> =
+                            tera::from_value(value).map_err(|_| Error::InvalidObjectElement)?;
+                        matrix.iterables.insert(key, value);
+                    }
+                }
             }
-            _ => Err(Error::InvalidElement),
-        })
-        .collect::<Result<Matrix, Error>>()
+            _ => return Err(Error::InvalidElement),
+        }
+    }
+
+    // every combination is rendered with a `matrix` variable describing it.
+    if matrix.iterables.contains_key(RESERVED_KEY)
+        || matrix
+            .include
+            .iter()
+            .any(|entry| entry.contains_key(RESERVED_KEY))
+    {
+        return Err(Error::ReservedKey);
+    }
+
+    // includes may add keys that aren't iterables, but excludes can only
+    // remove combinations of the iterables.
+    if let Some(key) = matrix
+        .exclude
+        .iter()
+        .flat_map(IndexMap::keys)
+        .find(|key| !matrix.iterables.contains_key(*key))
+    {
+        return Err(Error::UnknownRuleKey {
+            rule: "exclude",
+            key: key.clone(),
+        });
+    }
+    if let Some(flavors) = matrix.iterables.get("flavor") {
+        if let Some(flavor) = matrix
+            .include
+            .iter()
+            .filter_map(|entry| entry.get("flavor"))
+            .find(|flavor| !flavors.contains(flavor))
+        {
+            return Err(Error::UnknownIncludeFlavor {
+                flavor: flavor.clone(),
+            });
+        }
+    }
+    let product = matrix.product();
+    for entry in &matrix.include {
+        let missing = matrix
+            .iterables
+            .keys()
+            .find(|key| !entry.contains_key(*key));
+        if let Some(key) = missing {
+            if !product
+                .iter()
+                .any(|combination| matches(entry, combination))
+            {
+                return Err(Error::IncompleteInclude { key: key.clone() });
+            }
+        }
+    }
+
+    Ok(matrix)
 }
 
-fn magic_iterables(only_flavor: Option<FlavorName>) -> HashMap<&'static str, Vec<String>> {
-    HashMap::from([
-        (
-            "flavor",
-            only_flavor.map_or_else(
-                || {
-                    catppuccin::PALETTE
-                        .into_iter()
-                        .map(|flavor| flavor.identifier().to_string())
-                        .collect::<Vec<String>>()
-                },
-                |flavor| vec![flavor.identifier().to_string()],
-            ),
-        ),
-        ("accent", ctp_accents()),
-    ])
+impl Matrix {
+    /// Replace the values of an iterable, dropping the include entries that
+    /// use a value which is no longer in it.
+    pub fn narrow(&mut self, key: &str, values: Vec<String>) {
+        self.include
+            .retain(|entry| entry.get(key).is_none_or(|value| values.contains(value)));
+        self.iterables.insert(key.to_string(), values);
+    }
+
+    /// Every combination to render: the cartesian product of the iterables,
+    /// without the excluded combinations, followed by the included ones.
+    ///
+    /// Each include entry adds its other keys to the combinations that match
+    /// its values of the iterables, replacing values added by earlier entries
+    /// but never those of the iterables. An entry that matches no combination
+    /// is added as a combination of its own, if it has a value for every
+    /// iterable.
+    ///
+    /// The product varies the last declared iterable fastest, so the order is
+    /// stable between runs.
+    #[must_use]
+    pub fn combinations(&self) -> Vec<Combination> {
+        let mut combinations = self.product();
+        let original = combinations.len();
+        for entry in &self.include {
+            let mut matched = false;
+            for combination in combinations[..original]
+                .iter_mut()
+                .filter(|combination| matches(entry, &combination[..self.iterables.len()]))
+            {
+                matched = true;
+                for (key, value) in entry {
+                    if self.iterables.contains_key(key) {
+                        continue;
+                    }
+                    match combination.iter_mut().find(|(k, _)| k == key) {
+                        Some((_, v)) => v.clone_from(value),
+                        None => combination.push((key.clone(), value.clone())),
+                    }
+                }
+            }
+            if matched || !self.iterables.keys().all(|key| entry.contains_key(key)) {
+                continue;
+            }
+
+            let combination = self
+                .iterables
+                .keys()
+                .map(|key| (key.clone(), entry[key].clone()))
+                .chain(
+                    entry
+                        .iter()
+                        .filter(|(key, _)| !self.iterables.contains_key(*key))
+                        .map(|(key, value)| (key.clone(), value.clone())),
+                )
+                .collect();
+            if !combinations.contains(&combination) {
+                combinations.push(combination);
+            }
+        }
+        combinations
+    }
+
+    /// The cartesian product of the iterables, without the excluded
+    /// combinations.
+    fn product(&self) -> Vec<Combination> {
+        self.iterables
+            .iter()
+            .map(|(key, iterable)| iterable.iter().map(move |v| (key.clone(), v.clone())))
+            .multi_cartesian_product()
+            .filter(|combination| !self.exclude.iter().any(|rule| matches(rule, combination)))
+            .collect()
+    }
+}
+
+/// Whether every value in `rule` that is set for a key of `combination` is
+/// the same in both.
+fn matches(rule: &IndexMap<String, String>, combination: &[(String, String)]) -> bool {
+    combination
+        .iter()
+        .all(|(key, value)| rule.get(key).is_none_or(|v| v == value))
+}
+
+fn rules(value: tera::Value, rule: &'static str) -> Result<Vec<IndexMap<String, String>>, Error> {
+    let entries: Vec<IndexMap<String, String>> =
+        tera::from_value(value).map_err(|_| Error::InvalidRule { rule })?;
+    if entries.iter().any(IndexMap::is_empty) {
+        return Err(Error::EmptyRule { rule });
+    }
+    Ok(entries)
+}
+
+/// Names of the iterables that don't need their values listed.
+pub const MAGIC_ITERABLES: [&str; 2] = ["flavor", "accent"];
+
+fn magic_iterables(flavors: Vec<String>) -> HashMap<&'static str, Vec<String>> {
+    HashMap::from([("flavor", flavors), ("accent", ctp_accents())])
 }
 
 fn ctp_accents() -> Vec<String> {
@@ -71,3 +250,192 @@ fn ctp_accents() -> Vec<String> {
         .map(|c| c.name.identifier().to_string())
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn matrix(value: serde_json::Value, flavors: &[&str]) -> Result<Matrix, Error> {
+        let values = tera::from_value(tera::to_value(value).expect("test value is always valid"))
+            .expect("test value is always an array");
+        from_values(values, flavors.iter().map(ToString::to_string).collect())
+    }
+
+    fn has(combinations: &[Combination], flavor: &str, variant: &str) -> bool {
+        combinations.iter().any(|combination| {
+            combination.contains(&("flavor".to_string(), flavor.to_string()))
+                && combination.contains(&("variant".to_string(), variant.to_string()))
+        })
+    }
+
+    #[test]
+    fn exclude_and_include() {
+        let matrix = matrix(
+            json!([
+                "flavor",
+                { "variant": ["light", "dark"] },
+                { "exclude": [{ "flavor": "latte", "variant": "dark" }] },
+                { "include": [{ "flavor": "mocha", "variant": "oled" }] },
+            ]),
+            &["latte", "mocha"],
+        )
+        .expect("matrix is valid");
+        let combinations = matrix.combinations();
+        assert_eq!(combinations.len(), 4);
+        assert!(has(&combinations, "latte", "light"));
+        assert!(!has(&combinations, "latte", "dark"));
+        assert!(has(&combinations, "mocha", "dark"));
+        assert!(has(&combinations, "mocha", "oled"));
+    }
+
+    #[test]
+    fn declaration_order() {
+        let matrix = matrix(
+            json!([{ "variant": ["light", "dark"] }, "flavor"]),
+            &["latte", "mocha"],
+        )
+        .expect("matrix is valid");
+        let order = matrix
+            .combinations()
+            .into_iter()
+            .map(|combination| combination.into_iter().map(|(_, value)| value).join("-"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            order,
+            ["light-latte", "light-mocha", "dark-latte", "dark-mocha"]
+        );
+    }
+
+    #[test]
+    fn partial_exclude() {
+        let matrix = matrix(
+            json!([
+                "flavor",
+                { "variant": ["light", "dark"] },
+                { "exclude": [{ "variant": "dark" }] },
+            ]),
+            &["latte", "mocha"],
+        )
+        .expect("matrix is valid");
+        assert_eq!(matrix.combinations().len(), 2);
+    }
+
+    #[test]
+    fn include_extends_matching_combinations() {
+        let matrix = matrix(
+            json!([
+                "flavor",
+                { "variant": ["light", "dark"] },
+                { "include": [
+                    { "variant": "dark", "shade": "deep" },
+                    { "flavor": "mocha", "variant": "dark", "shade": "deeper" },
+                    { "flavor": "mocha", "variant": "light", "variant_name": "day" },
+                ] },
+            ]),
+            &["latte", "mocha"],
+        )
+        .expect("matrix is valid");
+        let combinations = matrix.combinations();
+        assert_eq!(combinations.len(), 4);
+        let shade = |flavor: &str| {
+            combinations
+                .iter()
+                .find(|combination| {
+                    combination.contains(&("flavor".to_string(), flavor.to_string()))
+                        && combination.contains(&("variant".to_string(), "dark".to_string()))
+                })
+                .and_then(|combination| combination.iter().find(|(key, _)| key == "shade"))
+                .map(|(_, value)| value.as_str())
+        };
+        assert_eq!(shade("latte"), Some("deep"));
+        assert_eq!(shade("mocha"), Some("deeper"));
+        assert!(combinations
+            .iter()
+            .any(|combination| combination
+                .contains(&("variant_name".to_string(), "day".to_string()))));
+    }
+
+    #[test]
+    fn include_for_unrendered_flavor_is_skipped() {
+        let mut matrix = matrix(
+            json!([
+                "flavor",
+                { "include": [{ "flavor": "latte", "variant": "oled" }] },
+            ]),
+            &["latte", "mocha"],
+        )
+        .expect("matrix is valid");
+        matrix.narrow("flavor", vec!["mocha".to_string()]);
+        assert_eq!(
+            matrix.combinations(),
+            [[("flavor".to_string(), "mocha".to_string())]]
+        );
+    }
+
+    #[test]
+    fn narrowing_drops_includes() {
+        let mut matrix = matrix(
+            json!([
+                "flavor",
+                { "variant": ["light", "dark"] },
+                { "include": [{ "flavor": "mocha", "variant": "oled" }] },
+            ]),
+            &["mocha"],
+        )
+        .expect("matrix is valid");
+        matrix.narrow("variant", vec!["dark".to_string()]);
+        let combinations = matrix.combinations();
+        assert_eq!(combinations.len(), 1);
+        assert!(has(&combinations, "mocha", "dark"));
+    }
+
+    #[test]
+    fn invalid_rules() {
+        let flavors = &["latte"];
+        assert!(matches!(
+            matrix(
+                json!(["flavor", { "exclude": [{ "accent": "red" }] }]),
+                flavors
+            ),
+            Err(Error::UnknownRuleKey {
+                rule: "exclude",
+                ..
+            })
+        ));
+        assert!(matches!(
+            matrix(
+                json!(["flavor", "accent", { "include": [{ "accent": "rainbow" }] }]),
+                flavors
+            ),
+            Err(Error::IncompleteInclude { key }) if key == "flavor"
+        ));
+        assert!(matches!(
+            matrix(
+                json!(["flavor", { "include": [{ "flavor": "mocah" }] }]),
+                flavors
+            ),
+            Err(Error::UnknownIncludeFlavor { flavor }) if flavor == "mocah"
+        ));
+        assert!(matches!(
+            matrix(json!(["flavor", { "matrix": ["a", "b"] }]), flavors),
+            Err(Error::ReservedKey)
+        ));
+        assert!(matches!(
+            matrix(
+                json!(["flavor", { "include": [{ "flavor": "latte", "matrix": "a" }] }]),
+                flavors
+            ),
+            Err(Error::ReservedKey)
+        ));
+        assert!(matches!(
+            matrix(json!(["flavor", { "exclude": [{}] }]), flavors),
+            Err(Error::EmptyRule { rule: "exclude" })
+        ));
+        assert!(matches!(
+            matrix(json!(["flavor", { "include": ["latte"] }]), flavors),
+            Err(Error::InvalidRule { rule: "include" })
+        ));
+    }
+}
@@ -1,7 +1,13 @@
+use std::{collections::HashMap, path::Path};
+
 use css_colors::Color as _;
 use indexmap::IndexMap;
 
-use crate::cli::ColorOverrides;
+use crate::{
+    colorspace::{Lab, Lch},
+    contrast,
+    simulation::{self, Deficiency},
+};
 
 // a frankenstein mix of Catppuccin & css_colors types to get all the
 // functionality we want.
@@ -27,6 +33,8 @@ pub struct Color {
     pub hex: String,
     pub rgb: RGB,
     pub hsl: HSL,
+    pub oklab: OKLab,
+    pub oklch: OKLCH,
     pub opacity: u8,
 }
 
@@ -44,10 +52,233 @@ pub struct HSL {
     pub l: f32,
 }
 
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct OKLab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct OKLCH {
+    pub l: f32,
+    pub c: f32,
+    pub h: f32,
+}
+
+impl From<&RGB> for OKLab {
+    fn from(rgb: &RGB) -> Self {
+        let lab = Lab::from_rgb(rgb.r, rgb.g, rgb.b);
+        Self {
+            l: lab.l as f32,
+            a: lab.a as f32,
+            b: lab.b as f32,
+        }
+    }
+}
+
+impl From<&RGB> for OKLCH {
+    fn from(rgb: &RGB) -> Self {
+        let lch = Lch::from_rgb(rgb.r, rgb.g, rgb.b);
+        Self {
+            l: lch.l as f32,
+            c: lch.c as f32,
+            h: lch.h as f32,
+        }
+    }
+}
+
+/// Hex codes that replace colors in the palette, for every flavor under `all`
+/// or for a single flavor under its identifier.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct ColorOverrides {
+    #[serde(default)]
+    pub all: HashMap<String, String>,
+    #[serde(default)]
+    pub latte: HashMap<String, String>,
+    #[serde(default)]
+    pub frappe: HashMap<String, String>,
+    #[serde(default)]
+    pub macchiato: HashMap<String, String>,
+    #[serde(default)]
+    pub mocha: HashMap<String, String>,
+    /// Overrides for flavors from a custom palette.
+    #[serde(flatten)]
+    pub custom: HashMap<String, HashMap<String, String>>,
+}
+
+impl ColorOverrides {
+    /// The overrides for the flavor with the given identifier.
+    #[must_use]
+    pub fn flavor(&self, identifier: &str) -> Option<&HashMap<String, String>> {
+        match identifier {
+            "latte" => Some(&self.latte),
+            "frappe" => Some(&self.frappe),
+            "macchiato" => Some(&self.macchiato),
+            "mocha" => Some(&self.mocha),
+            _ => self.custom.get(identifier),
+        }
+    }
+}
+
+/// A palette loaded from a file with `--palette`.
+///
+/// This follows the [`Palette`] schema, but only the name, hex and accent
+/// fields of each color are read. The other color formats are derived from
+/// the hex code, so a palette dumped by a template can be loaded back in.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct CustomPalette {
+    pub flavors: IndexMap<String, CustomFlavor>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct CustomFlavor {
+    pub name: String,
+    pub identifier: Option<String>,
+    pub dark: bool,
+    pub light: Option<bool>,
+    pub colors: IndexMap<String, CustomColor>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct CustomColor {
+    pub name: String,
+    pub identifier: Option<String>,
+    #[serde(default)]
+    pub accent: bool,
+    pub hex: String,
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Failed to parse hex color: {0}")]
     ParseHex(#[from] std::num::ParseIntError),
+
+    #[error("Failed to read palette file {path}")]
+    ReadPalette {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Palette file {path} is invalid: {message}")]
+    ParsePalette { path: String, message: String },
+
+    #[error("Palette has no flavors")]
+    NoFlavors,
+
+    #[error("Flavor {flavor} has no colors")]
+    NoColors { flavor: String },
+
+    #[error("{key} has identifier {identifier}, which does not match its key")]
+    IdentifierMismatch { key: String, identifier: String },
+
+    #[error(
+        "{identifier} is not a valid identifier, use only letters, digits and underscores and do not start with a digit"
+    )]
+    InvalidIdentifier { identifier: String },
+
+    #[error("Flavor {flavor} cannot be both dark and light")]
+    DarkAndLight { flavor: String },
+
+    #[error("Color overrides name flavor {flavor}, which is not in the palette")]
+    UnknownOverrideFlavor { flavor: String },
+
+    #[error("Color {flavor}.{color} has invalid hex code {hex}, expected RRGGBB or RRGGBBAA")]
+    InvalidHex {
+        flavor: String,
+        color: String,
+        hex: String,
+    },
+}
+
+impl CustomPalette {
+    /// Load a palette from a JSON or YAML file, chosen by its extension.
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let contents = std::fs::read_to_string(path).map_err(|e| Error::ReadPalette {
+            path: path.display().to_string(),
+            source: e,
+        })?;
+        let is_yaml = path
+            .extension()
+            .is_some_and(|ext| ext == "yaml" || ext == "yml");
+        let palette: Self = if is_yaml {
+            serde_yaml::from_str(&contents).map_err(|e| e.to_string())
+        } else {
+            serde_json::from_str(&contents).map_err(|e| e.to_string())
+        }
+        .map_err(|message| Error::ParsePalette {
+            path: path.display().to_string(),
+            message,
+        })?;
+        palette.validate()?;
+        Ok(palette)
+    }
+
+    /// Check that the palette can be rendered: every flavor has colors, every
+    /// identifier is usable as a template variable and matches its key, and
+    /// every hex code parses.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.flavors.is_empty() {
+            return Err(Error::NoFlavors);
+        }
+        for (key, flavor) in &self.flavors {
+            validate_identifier(key, flavor.identifier.as_deref())?;
+            if flavor.colors.is_empty() {
+                return Err(Error::NoColors {
+                    flavor: key.clone(),
+                });
+            }
+            if flavor.light == Some(flavor.dark) && flavor.dark {
+                return Err(Error::DarkAndLight {
+                    flavor: key.clone(),
+                });
+            }
+            for (color_key, color) in &flavor.colors {
+                validate_identifier(color_key, color.identifier.as_deref())?;
+                if parse_hex(&color.hex).is_none() {
+                    return Err(Error::InvalidHex {
+                        flavor: key.clone(),
+                        color: color_key.clone(),
+                        hex: color.hex.clone(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn validate_identifier(key: &str, identifier: Option<&str>) -> Result<(), Error> {
+    if let Some(identifier) = identifier.filter(|&identifier| identifier != key) {
+        return Err(Error::IdentifierMismatch {
+            key: key.to_string(),
+            identifier: identifier.to_string(),
+        });
+    }
+    let valid = key
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidIdentifier {
+            identifier: key.to_string(),
+        })
+    }
+}
+
+/// parse an `RRGGBB` or `RRGGBBAA` hex code, with an optional leading `#`.
+fn parse_hex(hex: &str) -> Option<([u8; 3], u8)> {
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if !matches!(hex.len(), 6 | 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    let opacity = if hex.len() == 8 { byte(6)? } else { 255 };
+    Some(([byte(0)?, byte(2)?, byte(4)?], opacity))
 }
 
 /// attempt to canonicalize a hex string, optionally capitalizing it and adding a prefix.
@@ -67,7 +298,7 @@ fn format_hex(hex: &str, capitalize_hex_strings: bool, hex_prefix: Option<&str>)
 
 fn color_from_hex(
     hex: &str,
-    blueprint: &catppuccin::Color,
+    blueprint: &Color,
     capitalize_hex_strings: bool,
     hex_prefix: Option<&str>,
 ) -> Result<Color, Error> {
@@ -80,16 +311,18 @@ fn color_from_hex(
     let hsl = css_colors::rgb(rgb.r, rgb.g, rgb.b).to_hsl();
     let hex = format_hex(hex, capitalize_hex_strings, hex_prefix);
     Ok(Color {
-        name: blueprint.name.to_string(),
-        identifier: blueprint.name.identifier().to_string(),
+        name: blueprint.name.clone(),
+        identifier: blueprint.identifier.clone(),
         accent: blueprint.accent,
         hex,
-        rgb,
         hsl: HSL {
             h: hsl.h.degrees(),
             s: hsl.s.as_f32(),
             l: hsl.l.as_f32(),
         },
+        oklab: (&rgb).into(),
+        oklch: (&rgb).into(),
+        rgb,
         opacity: 255,
     })
 }
@@ -100,68 +333,70 @@ fn color_from_catppuccin(
     hex_prefix: Option<&str>,
 ) -> Color {
     let hex = format_hex(&color.hex.to_string(), capitalize_hex_strings, hex_prefix);
+    let rgb = RGB {
+        r: color.rgb.r,
+        g: color.rgb.g,
+        b: color.rgb.b,
+    };
     Color {
         name: color.name.to_string(),
         identifier: color.name.identifier().to_string(),
         accent: color.accent,
         hex,
-        rgb: RGB {
-            r: color.rgb.r,
-            g: color.rgb.g,
-            b: color.rgb.b,
-        },
         hsl: HSL {
             h: color.hsl.h.round() as u16,
             s: color.hsl.s as f32,
             l: color.hsl.l as f32,
         },
+        oklab: (&rgb).into(),
+        oklch: (&rgb).into(),
+        rgb,
         opacity: 255,
     }
 }
 
-/// Build a [`Palette`] from [`catppuccin::PALETTE`], optionally applying color overrides.
-pub fn build_palette(
+fn color_from_custom(
+    color: &CustomColor,
+    flavor: &str,
+    identifier: &str,
     capitalize_hex_strings: bool,
     hex_prefix: Option<&str>,
-    color_overrides: Option<&ColorOverrides>,
-) -> Result<Palette, Error> {
-    // make a `Color` from a `catppuccin::Color`, taking into account `color_overrides`.
-    // overrides apply in this order:
-    // 1. base color
-    // 2. "all" override
-    // 3. flavor override
-    let make_color =
-        |color: &catppuccin::Color, flavor_name: catppuccin::FlavorName| -> Result<Color, Error> {
-            let flavor_override = color_overrides
-                .map(|co| match flavor_name {
-                    catppuccin::FlavorName::Latte => &co.latte,
-                    catppuccin::FlavorName::Frappe => &co.frappe,
-                    catppuccin::FlavorName::Macchiato => &co.macchiato,
-                    catppuccin::FlavorName::Mocha => &co.mocha,
-                })
-                .and_then(|o| o.get(color.name.identifier()).cloned())
-                .map(|s| color_from_hex(&s, color, capitalize_hex_strings, hex_prefix))
-                .transpose()?;
-
-            let all_override = color_overrides
-                .and_then(|co| co.all.get(color.name.identifier()).cloned())
-                .map(|s| color_from_hex(&s, color, capitalize_hex_strings, hex_prefix))
-                .transpose()?;
-
-            Ok(flavor_override.or(all_override).unwrap_or_else(|| {
-                color_from_catppuccin(color, capitalize_hex_strings, hex_prefix)
-            }))
-        };
+) -> Result<Color, Error> {
+    let ([r, g, b], opacity) = parse_hex(&color.hex).ok_or_else(|| Error::InvalidHex {
+        flavor: flavor.to_string(),
+        color: identifier.to_string(),
+        hex: color.hex.clone(),
+    })?;
+    let rgb = RGB { r, g, b };
+    Ok(Color {
+        name: color.name.clone(),
+        identifier: identifier.to_string(),
+        accent: color.accent,
+        hex: format_hex(
+            &rgb_to_hex(&rgb, opacity),
+            capitalize_hex_strings,
+            hex_prefix,
+        ),
+        hsl: rgb_to_hsl(&rgb),
+        oklab: (&rgb).into(),
+        oklch: (&rgb).into(),
+        rgb,
+        opacity,
+    })
+}
 
+fn catppuccin_palette(capitalize_hex_strings: bool, hex_prefix: Option<&str>) -> Palette {
     let mut flavors = IndexMap::new();
     for flavor in &catppuccin::PALETTE {
-        let mut colors = IndexMap::new();
-        for color in flavor {
-            colors.insert(
-                color.name.identifier().to_string(),
-                make_color(color, flavor.name)?,
-            );
-        }
+        let colors = flavor
+            .into_iter()
+            .map(|color| {
+                (
+                    color.name.identifier().to_string(),
+                    color_from_catppuccin(color, capitalize_hex_strings, hex_prefix),
+                )
+            })
+            .collect();
         flavors.insert(
             flavor.name.identifier().to_string(),
             Flavor {
@@ -173,14 +408,127 @@ pub fn build_palette(
             },
         );
     }
+    Palette { flavors }
+}
+
+fn custom_palette(
+    palette: &CustomPalette,
+    capitalize_hex_strings: bool,
+    hex_prefix: Option<&str>,
+) -> Result<Palette, Error> {
+    let mut flavors = IndexMap::new();
+    for (identifier, flavor) in &palette.flavors {
+        let mut colors = IndexMap::new();
+        for (color_identifier, color) in &flavor.colors {
+            colors.insert(
+                color_identifier.clone(),
+                color_from_custom(
+                    color,
+                    identifier,
+                    color_identifier,
+                    capitalize_hex_strings,
+                    hex_prefix,
+                )?,
+            );
+        }
+        flavors.insert(
+            identifier.clone(),
+            Flavor {
+                name: flavor.name.clone(),
+                identifier: identifier.clone(),
+                dark: flavor.dark,
+                light: flavor.light.unwrap_or(!flavor.dark),
+                colors,
+            },
+        );
+    }
     Ok(Palette { flavors })
 }
 
+/// The identifiers of the flavors in the palette that [`build_palette`] builds.
+#[must_use]
+pub fn flavor_identifiers(custom: Option<&CustomPalette>) -> Vec<String> {
+    custom.map_or_else(
+        || {
+            catppuccin::PALETTE
+                .into_iter()
+                .map(|flavor| flavor.identifier().to_string())
+                .collect()
+        },
+        |custom| custom.flavors.keys().cloned().collect(),
+    )
+}
+
+/// Build a [`Palette`] from [`catppuccin::PALETTE`], or from a custom palette
+/// if one is given, optionally applying color overrides.
+pub fn build_palette(
+    capitalize_hex_strings: bool,
+    hex_prefix: Option<&str>,
+    color_overrides: Option<&ColorOverrides>,
+    custom: Option<&CustomPalette>,
+) -> Result<Palette, Error> {
+    let mut palette = custom.map_or_else(
+        || Ok(catppuccin_palette(capitalize_hex_strings, hex_prefix)),
+        |custom| custom_palette(custom, capitalize_hex_strings, hex_prefix),
+    )?;
+
+    let Some(color_overrides) = color_overrides else {
+        return Ok(palette);
+    };
+    if let Some(flavor) = color_overrides
+        .custom
+        .keys()
+        .find(|flavor| !palette.flavors.contains_key(*flavor))
+    {
+        return Err(Error::UnknownOverrideFlavor {
+            flavor: flavor.clone(),
+        });
+    }
+
+    // overrides apply in this order:
+    // 1. base color
+    // 2. "all" override
+    // 3. flavor override
+    for (flavor_identifier, flavor) in &mut palette.flavors {
+        let flavor_overrides = color_overrides.flavor(flavor_identifier);
+        for (identifier, color) in &mut flavor.colors {
+            let hex = flavor_overrides
+                .and_then(|o| o.get(identifier))
+                .or_else(|| color_overrides.all.get(identifier));
+            if let Some(hex) = hex {
+                *color = color_from_hex(hex, color, capitalize_hex_strings, hex_prefix)?;
+            }
+        }
+    }
+    Ok(palette)
+}
+
 impl Palette {
     #[must_use]
-    pub fn iter(&self) -> indexmap::map::Iter<String, Flavor> {
+    pub fn iter(&self) -> indexmap::map::Iter<'_, String, Flavor> {
         self.flavors.iter()
     }
+
+    /// Simulate how every color in the palette appears with a color vision
+    /// deficiency, keeping hex strings in the palette's format.
+    #[must_use]
+    pub fn simulate(
+        &self,
+        deficiency: Deficiency,
+        capitalize_hex_strings: bool,
+        hex_prefix: Option<&str>,
+    ) -> Self {
+        let mut palette = self.clone();
+        for color in palette
+            .flavors
+            .values_mut()
+            .flat_map(|flavor| flavor.colors.values_mut())
+        {
+            *color = color.simulate(deficiency, 1.0);
+            color.hex = format_hex(&color.hex, capitalize_hex_strings, hex_prefix);
+        }
+        palette
+    }
 }
 
 impl<'a> IntoIterator for &'a Palette {
@@ -194,7 +542,7 @@ impl<'a> IntoIterator for &'a Palette {
 
 impl Flavor {
     #[must_use]
-    pub fn iter(&self) -> indexmap::map::Iter<String, Color> {
+    pub fn iter(&self) -> indexmap::map::Iter<'_, String, Color> {
         self.colors.iter()
     }
 }
@@ -208,6 +556,48 @@ impl<'a> IntoIterator for &'a Flavor {
     }
 }
 
+/// Same as `css_colors`' RGB to HSL conversion, which can round the
+/// saturation of colors with a channel at 255 to slightly above 1 and panic.
+/// The arithmetic is kept identical so that the results are too.
+#[allow(clippy::suboptimal_flops, clippy::manual_midpoint)]
+fn rgb_to_hsl(rgb: &RGB) -> HSL {
+    let channel = |c: u8| f32::from(c) / 255.0;
+    let (red, green, blue) = (channel(rgb.r), channel(rgb.g), channel(rgb.b));
+    if rgb.r == rgb.g && rgb.g == rgb.b {
+        return HSL {
+            h: 0,
+            s: 0.0,
+            l: red,
+        };
+    }
+
+    let highest = rgb.r.max(rgb.g).max(rgb.b);
+    let (max, min) = (channel(highest), channel(rgb.r.min(rgb.g).min(rgb.b)));
+    let lightness = (max + min) / 2.0;
+    let saturation = if lightness < 0.5 {
+        (max - min) / (max + min)
+    } else {
+        (max - min) / (2.0 - (max + min))
+    };
+    let hue = if highest == rgb.r {
+        60.0 * (green - blue) / (max - min)
+    } else if highest == rgb.g {
+        120.0 + 60.0 * (blue - red) / (max - min)
+    } else {
+        240.0 + 60.0 * (red - green) / (max - min)
+    };
+    HSL {
+        h: (hue.round() as i32).rem_euclid(360) as u16,
+        s: quantize(saturation),
+        l: quantize(lightness),
+    }
+}
+
+/// `css_colors` stores ratios as a `u8`.
+fn quantize(ratio: f32) -> f32 {
+    (ratio.clamp(0.0, 1.0) * 255.0).round() / 255.0
+}
+
 fn rgb_to_hex(rgb: &RGB, opacity: u8) -> String {
     if opacity < 255 {
         format!("{:02x}{:02x}{:02x}{:02x}", rgb.r, rgb.g, rgb.b, opacity)
@@ -235,6 +625,8 @@ impl Color {
             identifier: blueprint.identifier.clone(),
             accent: blueprint.accent,
             hex: rgb_to_hex(&rgb, opacity),
+            oklab: (&rgb).into(),
+            oklch: (&rgb).into(),
             rgb,
             hsl,
             opacity,
@@ -242,23 +634,20 @@ impl Color {
     }
 
     fn from_rgba(rgba: css_colors::RGBA, blueprint: &Self) -> Self {
-        let hsl = rgba.to_hsl();
         let rgb = RGB {
             r: rgba.r.as_u8(),
             g: rgba.g.as_u8(),
             b: rgba.b.as_u8(),
         };
-        let hsl = HSL {
-            h: hsl.h.degrees(),
-            s: hsl.s.as_f32(),
-            l: hsl.l.as_f32(),
-        };
+        let hsl = rgb_to_hsl(&rgb);
         let opacity = rgba.a.as_u8();
         Self {
             name: blueprint.name.clone(),
             identifier: blueprint.identifier.clone(),
             accent: blueprint.accent,
             hex: rgb_to_hex(&rgb, opacity),
+            oklab: (&rgb).into(),
+            oklch: (&rgb).into(),
             rgb,
             hsl,
             opacity,
@@ -276,6 +665,98 @@ impl Color {
         Self::from_rgba(result, blueprint)
     }
 
+    fn from_oklch(lch: Lch, opacity: u8, blueprint: &Self) -> Self {
+        Self::from_rgb(lch.to_rgb(), opacity, blueprint)
+    }
+
+    fn from_rgb([r, g, b]: [u8; 3], opacity: u8, blueprint: &Self) -> Self {
+        let rgba = css_colors::RGBA {
+            r: css_colors::Ratio::from_u8(r),
+            g: css_colors::Ratio::from_u8(g),
+            b: css_colors::Ratio::from_u8(b),
+            a: css_colors::Ratio::from_u8(opacity),
+        };
+        Self::from_rgba(rgba, blueprint)
+    }
+
+    fn to_oklch(&self) -> Lch {
+        Lch::from_rgb(self.rgb.r, self.rgb.g, self.rgb.b)
+    }
+
+    /// Mix two colors by interpolating in `OKLab` space.
+    #[must_use]
+    pub fn mix_oklab(base: &Self, blend: &Self, amount: f64) -> Self {
+        let amount = amount.clamp(0.0, 1.0);
+        let lab = Lab::from_rgb(base.rgb.r, base.rgb.g, base.rgb.b)
+            .mix(Lab::from_rgb(blend.rgb.r, blend.rgb.g, blend.rgb.b), amount);
+        Self::from_oklch(lab.to_lch(), mix_opacity(base, blend, amount), base)
+    }
+
+    /// Mix two colors by interpolating in `OKLCH` space along the shortest hue arc.
+    #[must_use]
+    pub fn mix_oklch(base: &Self, blend: &Self, amount: f64) -> Self {
+        let amount = amount.clamp(0.0, 1.0);
+        let lch = base.to_oklch().mix(blend.to_oklch(), amount);
+        Self::from_oklch(lch, mix_opacity(base, blend, amount), base)
+    }
+
+    /// Apply a transformation to the color's `OKLCH` representation.
+    #[must_use]
+    pub fn map_oklch(&self, f: impl FnOnce(Lch) -> Lch) -> Self {
+        Self::from_oklch(f(self.to_oklch()), self.opacity, self)
+    }
+
+    /// Simulate how the color appears with a color vision deficiency.
+    ///
+    /// `severity` ranges from 0 (normal vision) to 1 (complete deficiency).
+    #[must_use]
+    pub fn simulate(&self, deficiency: Deficiency, severity: f64) -> Self {
+        let rgb = [self.rgb.r, self.rgb.g, self.rgb.b];
+        Self::from_rgb(
+            simulation::simulate(rgb, deficiency, severity),
+            self.opacity,
+            self,
+        )
+    }
+
+    /// The perceptual difference (ΔEOK) between two colors, i.e. their
+    /// euclidean distance in `OKLab` space.
+    #[must_use]
+    pub fn distance(&self, other: &Self) -> f64 {
+        let a = Lab::from_rgb(self.rgb.r, self.rgb.g, self.rgb.b);
+        let b = Lab::from_rgb(other.rgb.r, other.rgb.g, other.rgb.b);
+        (a.l - b.l).hypot(a.a - b.a).hypot(a.b - b.b)
+    }
+
+    /// The color's RGB channels, composited over `background` if it's translucent.
+    fn flatten_over(&self, background: &Self) -> [u8; 3] {
+        let alpha = f64::from(self.opacity) / 255.0;
+        let blend = |fg: u8, bg: u8| {
+            f64::from(fg)
+                .mul_add(alpha, f64::from(bg) * (1.0 - alpha))
+                .round() as u8
+        };
+        [
+            blend(self.rgb.r, background.rgb.r),
+            blend(self.rgb.g, background.rgb.g),
+            blend(self.rgb.b, background.rgb.b),
+        ]
+    }
+
+    /// The WCAG 2 contrast ratio of this color on `background`, from 1 to 21.
+    #[must_use]
+    pub fn contrast_ratio(&self, background: &Self) -> f64 {
+        let background_rgb = [background.rgb.r, background.rgb.g, background.rgb.b];
+        contrast::wcag(self.flatten_over(background), background_rgb)
+    }
+
+    /// The APCA lightness contrast (Lc) of this color as text on `background`.
+    #[must_use]
+    pub fn apca_contrast(&self, background: &Self) -> f64 {
+        let background_rgb = [background.rgb.r, background.rgb.g, background.rgb.b];
+        contrast::apca(self.flatten_over(background), background_rgb)
+    }
+
     #[must_use]
     pub fn mod_hue(&self, hue: i32) -> Self {
         let mut hsl: css_colors::HSL = self.into();
@@ -372,6 +853,12 @@ impl Color {
     }
 }
 
+fn mix_opacity(base: &Color, blend: &Color, amount: f64) -> u8 {
+    f64::from(base.opacity)
+        .mul_add(amount, f64::from(blend.opacity) * (1.0 - amount))
+        .round() as u8
+}
+
 impl From<&Color> for css_colors::RGB {
     fn from(c: &Color) -> Self {
         Self {
@@ -413,3 +900,54 @@ impl From<&Color> for css_colors::HSLA {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `rgb_to_hsl` replaces `css_colors`' conversion for the results of
+    /// color functions, so it must give the same `hsl` values.
+    #[test]
+    fn rgb_to_hsl_matches_css_colors() {
+        let palette = build_palette(false, None, None, None).expect("palette can be built");
+        for (flavor, color) in palette
+            .flavors
+            .values()
+            .flat_map(|flavor| flavor.colors.values().map(move |color| (flavor, color)))
+        {
+            let expected = css_colors::rgb(color.rgb.r, color.rgb.g, color.rgb.b).to_hsl();
+            let actual = rgb_to_hsl(&color.rgb);
+            assert_eq!(
+                (actual.h, actual.s, actual.l),
+                (
+                    expected.h.degrees(),
+                    expected.s.as_f32(),
+                    expected.l.as_f32()
+                ),
+                "{} in {}",
+                color.identifier,
+                flavor.identifier
+            );
+        }
+
+        // and for the other colors that `add`, `sub` and `mod` can return,
+        // except those with a channel at 255, for which `css_colors` panics.
+        for (r, g, b) in itertools::iproduct!(
+            (0..255).step_by(6),
+            (0..255).step_by(6),
+            (0..255).step_by(6)
+        ) {
+            let expected = css_colors::rgb(r, g, b).to_hsl();
+            let actual = rgb_to_hsl(&RGB { r, g, b });
+            assert_eq!(
+                (actual.h, actual.s, actual.l),
+                (
+                    expected.h.degrees(),
+                    expected.s.as_f32(),
+                    expected.l.as_f32()
+                ),
+                "rgb({r}, {g}, {b})"
+            );
+        }
+    }
+}
@@ -0,0 +1,737 @@
+//! the rendering pipeline behind the `whiskers` binary, for rendering
+//! templates from other programs.
+//!
+//! ```
+//! use whiskers::render::{Options, Output, RenderRequest, Renderer};
+//!
+//! let mut renderer = Renderer::new(Options {
+//!     flavor: Some("mocha".to_string()),
+//!     ..Options::default()
+//! });
+//! let rendered = renderer.render(&RenderRequest::new("{{ red.hex }}"))?;
+//! if let Output::Single(contents) = rendered.output {
+//!     assert_eq!(contents, b"f38ba8");
+//! }
+//! # Ok::<(), whiskers::render::Error>(())
+//! ```
+
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use indexmap::{map::Entry, IndexMap};
+use itertools::Itertools as _;
+
+use crate::{
+    context::merge_values,
+    encoding, frontmatter,
+    matrix::{self, Matrix},
+    models::{self, ColorOverrides, CustomPalette},
+    simulation::Deficiency,
+    templating,
+};
+
+const FRONTMATTER_OPTIONS_SECTION: &str = "whiskers";
+
+/// Options that apply to every template rendered by a [`Renderer`].
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// Render a single flavor instead of all of them.
+    pub flavor: Option<String>,
+    /// Use a custom palette instead of the Catppuccin one.
+    pub palette: Option<CustomPalette>,
+    /// Replace colors in the palette with hex codes of your choosing.
+    pub color_overrides: Option<ColorOverrides>,
+    /// Frontmatter overrides, which also replace matrix iterables.
+    pub overrides: Option<HashMap<String, tera::Value>>,
+    /// Replace every color with how it appears with a color vision deficiency.
+    pub simulate: Option<Deficiency>,
+    /// A directory of partials, named by their path relative to it.
+    pub partials: Option<PathBuf>,
+    /// Number of matrix combinations to render in parallel. Defaults to the
+    /// number of CPUs.
+    pub jobs: Option<NonZeroUsize>,
+    /// `hex_prefix` for templates that don't set it in their frontmatter.
+    pub hex_prefix: Option<String>,
+    /// `capitalize_hex` for templates that don't set it in their frontmatter.
+    pub capitalize_hex: Option<bool>,
+}
+
+/// A template to render.
+#[derive(Clone, Debug)]
+pub struct RenderRequest {
+    /// The template, including its frontmatter.
+    pub source: String,
+    /// Name of the template in the Tera engine, as seen in error messages.
+    pub name: String,
+    /// Path of the template file. Partials set in the frontmatter are
+    /// relative to its directory.
+    pub path: Option<PathBuf>,
+    /// Fail if the template requires a different version of whiskers.
+    pub check_version: bool,
+}
+
+impl RenderRequest {
+    /// A request for a template that isn't read from a file.
+    #[must_use]
+    pub fn new(source: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            name: "template".to_string(),
+            path: None,
+            check_version: false,
+        }
+    }
+
+    /// The partials directory set in the template's frontmatter, if it sets
+    /// one and the frontmatter is valid.
+    #[must_use]
+    pub fn frontmatter_partials(&self) -> Option<PathBuf> {
+        let doc = frontmatter::parse(&self.source).ok()?;
+        let opts = doc.frontmatter.get(FRONTMATTER_OPTIONS_SECTION)?;
+        let opts: FrontmatterOptions = tera::from_value(opts.clone()).ok()?;
+        opts.partials
+            .map(|partials| self.relative_to_template(&partials))
+    }
+
+    /// Paths in the frontmatter are relative to the template itself.
+    fn relative_to_template(&self, path: &Path) -> PathBuf {
+        self.path
+            .as_deref()
+            .and_then(Path::parent)
+            .map_or_else(|| path.to_path_buf(), |parent| parent.join(path))
+    }
+}
+
+/// The result of rendering a template.
+#[derive(Debug)]
+pub struct Rendered {
+    /// What the template rendered to.
+    pub output: Output,
+    /// The version was checked, but the template doesn't declare which
+    /// versions of whiskers it supports.
+    pub missing_version: bool,
+}
+
+/// The rendered output of a template, which has a single file or one file
+/// per matrix combination.
+#[derive(Debug)]
+pub enum Output {
+    /// The encoded output of a template without a matrix.
+    Single(Vec<u8>),
+    /// A file for every combination of a matrix template, in matrix order.
+    Multi(Vec<File>),
+}
+
+/// The output of a single matrix combination.
+#[derive(Debug)]
+pub struct File {
+    /// The matrix values that the file was rendered with.
+    pub combination: matrix::Combination,
+    /// The rendered `filename` template.
+    pub filename: PathBuf,
+    /// The encoded output.
+    pub contents: Vec<u8>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Frontmatter is invalid")]
+    Frontmatter(#[from] frontmatter::Error),
+
+    #[error("Frontmatter `whiskers` section is invalid")]
+    InvalidOptions(#[source] serde_json::Error),
+
+    #[error("Frontmatter matrix is invalid")]
+    Matrix(#[from] matrix::Error),
+
+    #[error(
+        "Template requires whiskers version {required}, but you are running whiskers {running}"
+    )]
+    IncompatibleVersion {
+        required: semver::VersionReq,
+        running: semver::Version,
+    },
+
+    #[error("Value of {key} override is invalid")]
+    InvalidOverride {
+        key: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("Value of {key} override is not a list of strings")]
+    InvalidMatrixOverride { key: String },
+
+    #[error("Palette context cannot be built")]
+    Palette(#[from] models::Error),
+
+    #[error("Palette has no {flavor} flavor")]
+    UnknownFlavor { flavor: String },
+
+    #[error("Frontmatter partials could not be loaded")]
+    FrontmatterPartials(#[source] tera::Error),
+
+    #[error("Frontmatter partials can't be loaded in the browser, which has no filesystem")]
+    PartialsUnsupported,
+
+    #[error("Partials could not be loaded")]
+    Partials(#[source] tera::Error),
+
+    #[error("Template is invalid")]
+    InvalidTemplate(#[source] tera::Error),
+
+    #[error("Template render failed")]
+    Render(#[source] tera::Error),
+
+    #[error("Filename template is required for multi-output render")]
+    MissingFilename,
+
+    #[error("Frontmatter key `matrix` is reserved for the matrix context variable")]
+    ReservedKey,
+
+    #[error("Filename template render failed")]
+    Filename(#[source] tera::Error),
+
+    #[error("Failed to render matrix combination {combination}")]
+    Combination {
+        /// The combination, as `key=value` pairs.
+        combination: String,
+        #[source]
+        source: Box<Self>,
+    },
+}
+
+/// The `whiskers` frontmatter section as it is written, before the matrix is
+/// expanded and defaults are applied.
+///
+/// This is the only definition of the section's keys, which validation and the
+/// JSON schema are checked against. Unknown keys are ignored, so that a
+/// template using keys from a newer whiskers still reports the version it
+/// needs. `M` is the type of a matrix element, so that validation can check
+/// each element as it's deserialized.
+#[derive(serde::Deserialize)]
+pub(crate) struct FrontmatterOptions<M = tera::Value> {
+    pub version: Option<semver::VersionReq>,
+    pub matrix: Option<Vec<M>>,
+    pub filename: Option<String>,
+    pub hex_prefix: Option<String>,
+    pub capitalize_hex: Option<bool>,
+    pub partials: Option<PathBuf>,
+    // these are the fields of `encoding::Output`, which aren't flattened so
+    // that serde lists them with the other keys.
+    #[serde(default)]
+    pub encoding: encoding::Encoding,
+    #[serde(default)]
+    pub bom: bool,
+    pub line_endings: Option<encoding::LineEnding>,
+}
+
+impl<M> FrontmatterOptions<M> {
+    pub(crate) const fn output(&self) -> encoding::Output {
+        encoding::Output {
+            encoding: self.encoding,
+            bom: self.bom,
+            line_endings: self.line_endings,
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+struct TemplateOptions {
+    version: Option<semver::VersionReq>,
+    matrix: Option<Matrix>,
+    filename: Option<String>,
+    hex_prefix: Option<String>,
+    capitalize_hex: bool,
+    partials: Option<PathBuf>,
+    output: encoding::Output,
+}
+
+impl TemplateOptions {
+    fn from_frontmatter(
+        frontmatter: &HashMap<String, tera::Value>,
+        options: &Options,
+    ) -> Result<Self, Error> {
+        let flavors = models::flavor_identifiers(options.palette.as_ref());
+        if let Some(opts) = frontmatter.get(FRONTMATTER_OPTIONS_SECTION) {
+            let opts: FrontmatterOptions =
+                tera::from_value(opts.clone()).map_err(Error::InvalidOptions)?;
+            let output = opts.output();
+            let mut matrix = opts
+                .matrix
+                .map(|m| matrix::from_values(m, flavors))
+                .transpose()?;
+            if let (Some(matrix), Some(flavor)) = (&mut matrix, &options.flavor) {
+                if matrix.iterables.contains_key("flavor") {
+                    matrix.narrow("flavor", vec![flavor.clone()]);
+                }
+            }
+            Ok(Self {
+                version: opts.version,
+                matrix,
+                filename: opts.filename,
+                hex_prefix: opts.hex_prefix.or_else(|| options.hex_prefix.clone()),
+                capitalize_hex: opts
+                    .capitalize_hex
+                    .or(options.capitalize_hex)
+                    .unwrap_or_default(),
+                partials: opts.partials,
+                output,
+            })
+        } else {
+            Ok(Self {
+                hex_prefix: options.hex_prefix.clone(),
+                capitalize_hex: options.capitalize_hex.unwrap_or_default(),
+                ..Self::default()
+            })
+        }
+    }
+}
+
+/// Renders templates with the same options, reusing the palette between
+/// templates with the same hex formatting.
+#[derive(Debug)]
+pub struct Renderer {
+    options: Options,
+    palettes: IndexMap<(bool, Option<String>), models::Palette>,
+}
+
+impl Renderer {
+    /// A renderer that hasn't built any palettes yet.
+    #[must_use]
+    pub fn new(options: Options) -> Self {
+        Self {
+            options,
+            palettes: IndexMap::new(),
+        }
+    }
+
+    /// Render a template into memory.
+    pub fn render(&mut self, request: &RenderRequest) -> Result<Rendered, Error> {
+        let doc = frontmatter::parse(&request.source)?;
+        let mut template_opts = TemplateOptions::from_frontmatter(&doc.frontmatter, &self.options)?;
+
+        let missing_version = request.check_version && check_version(&template_opts)?;
+
+        // merge frontmatter with overrides and add to Tera context
+        let frontmatter = self.apply_overrides(doc.frontmatter, &mut template_opts)?;
+        if template_opts.matrix.is_some() && frontmatter.contains_key(matrix::RESERVED_KEY) {
+            return Err(Error::ReservedKey);
+        }
+        let mut ctx = tera::Context::new();
+        for (key, value) in &frontmatter {
+            ctx.insert(key, &value);
+        }
+
+        // build the palette and add it to the templating context
+        let palette = match self.palettes.entry((
+            template_opts.capitalize_hex,
+            template_opts.hex_prefix.clone(),
+        )) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(build_palette(&self.options, &template_opts)?),
+        };
+        ctx.insert("flavors", &palette.flavors);
+        if let Some(ref flavor) = self.options.flavor {
+            let flavor = palette
+                .flavors
+                .get(flavor)
+                .ok_or_else(|| Error::UnknownFlavor {
+                    flavor: flavor.clone(),
+                })?;
+            ctx.insert("flavor", flavor);
+
+            // also throw in the flavor's colors for convenience
+            for (_, color) in flavor {
+                ctx.insert(&color.identifier, &color);
+            }
+        }
+
+        let tera = build_engine(&self.options, &template_opts, request, &doc.body)?;
+
+        let output = if let Some(ref matrix) = template_opts.matrix {
+            let filename_template = template_opts
+                .filename
+                .as_deref()
+                .ok_or(Error::MissingFilename)?;
+            let jobs = self.options.jobs.map_or_else(
+                || std::thread::available_parallelism().map_or(1, NonZeroUsize::get),
+                NonZeroUsize::get,
+            );
+            Output::Multi(render_multi_output(
+                matrix,
+                filename_template,
+                template_opts.output,
+                &ctx,
+                palette,
+                &tera,
+                &request.name,
+                jobs,
+            )?)
+        } else {
+            let result = tera.render(&request.name, &ctx).map_err(Error::Render)?;
+            Output::Single(template_opts.output.encode(&result))
+        };
+
+        Ok(Rendered {
+            output,
+            missing_version,
+        })
+    }
+
+    fn apply_overrides(
+        &self,
+        mut frontmatter: HashMap<String, tera::Value>,
+        template_opts: &mut TemplateOptions,
+    ) -> Result<HashMap<String, tera::Value>, Error> {
+        let Some(ref overrides) = self.options.overrides else {
+            return Ok(frontmatter);
+        };
+        for (key, value) in overrides {
+            frontmatter
+                .entry(key.clone())
+                .and_modify(|v| {
+                    *v = merge_values(v, value);
+                })
+                .or_insert(tera::to_value(value).map_err(|e| Error::InvalidOverride {
+                    key: key.clone(),
+                    source: e,
+                })?);
+
+            // overrides also work on matrix iterables
+            if let Some(ref mut matrix) = template_opts.matrix {
+                override_matrix(matrix, value, key)?;
+            }
+        }
+        Ok(frontmatter)
+    }
+}
+
+/// Check a template's frontmatter and syntax without rendering it.
+pub fn validate(request: &RenderRequest, options: &Options) -> Result<(), Error> {
+    let doc = frontmatter::parse(&request.source)?;
+    let template_opts = TemplateOptions::from_frontmatter(&doc.frontmatter, options)?;
+    if request.check_version {
+        check_version(&template_opts)?;
+    }
+    build_engine(options, &template_opts, request, &doc.body)?;
+    Ok(())
+}
+
+/// Build the Tera engine with the partials and the template body added.
+fn build_engine(
+    options: &Options,
+    template_opts: &TemplateOptions,
+    request: &RenderRequest,
+    body: &str,
+) -> Result<tera::Tera, Error> {
+    let mut tera = templating::make_engine();
+    if let Some(ref partials) = template_opts.partials {
+        if cfg!(target_family = "wasm") {
+            return Err(Error::PartialsUnsupported);
+        }
+        templating::add_partials(&mut tera, &request.relative_to_template(partials))
+            .map_err(Error::FrontmatterPartials)?;
+    }
+    if let Some(ref partials) = options.partials {
+        templating::add_partials(&mut tera, partials).map_err(Error::Partials)?;
+    }
+    tera.add_raw_template(&request.name, body)
+        .map_err(Error::InvalidTemplate)?;
+    Ok(tera)
+}
+
+/// Check the template's version requirement, returning whether it's missing.
+fn check_version(template_opts: &TemplateOptions) -> Result<bool, Error> {
+    let running = semver::Version::parse(env!("CARGO_PKG_VERSION"))
+        .expect("CARGO_PKG_VERSION is always valid");
+    match template_opts.version {
+        Some(ref required) if !required.matches(&running) => Err(Error::IncompatibleVersion {
+            required: required.clone(),
+            running,
+        }),
+        Some(_) => Ok(false),
+        None => Ok(true),
+    }
+}
+
+fn build_palette(
+    options: &Options,
+    template_opts: &TemplateOptions,
+) -> Result<models::Palette, Error> {
+    let palette = models::build_palette(
+        template_opts.capitalize_hex,
+        template_opts.hex_prefix.as_deref(),
+        options.color_overrides.as_ref(),
+        options.palette.as_ref(),
+    )?;
+    Ok(match options.simulate {
+        Some(deficiency) => palette.simulate(
+            deficiency,
+            template_opts.capitalize_hex,
+            template_opts.hex_prefix.as_deref(),
+        ),
+        None => palette,
+    })
+}
+
+fn override_matrix(matrix: &mut Matrix, value: &tera::Value, key: &str) -> Result<(), Error> {
+    if !matrix.iterables.contains_key(key) {
+        return Ok(());
+    }
+
+    // if the override is a list, we can just replace the iterable.
+    if let Some(value_list) = value.as_array() {
+        let value_list = value_list
+            .iter()
+            .map(|v| v.as_str().map(ToString::to_string))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| Error::InvalidMatrixOverride {
+                key: key.to_string(),
+            })?;
+        matrix.narrow(key, value_list);
+    }
+    // if the override is a string, we instead replace the iterable with a
+    // single-element list containing the string.
+    else if let Some(value_string) = value.as_str() {
+        matrix.narrow(key, vec![value_string.to_string()]);
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn render_multi_output(
+    matrix: &Matrix,
+    filename_template: &str,
+    output: encoding::Output,
+    ctx: &tera::Context,
+    palette: &models::Palette,
+    tera: &tera::Tera,
+    template_name: &str,
+    jobs: usize,
+) -> Result<Vec<File>, Error> {
+    let combinations = matrix.combinations();
+
+    let render_combination = |ctx: &mut tera::Context,
+                              combination: &matrix::Combination|
+     -> Result<File, Error> {
+        for (key, value) in combination {
+            // expand flavor automatically to prevent requiring:
+            // `{% set flavor = flavors[flavor] %}`
+            // at the top of every template.
+            if key == "flavor" {
+                let flavor = palette
+                    .flavors
+                    .get(value)
+                    .ok_or_else(|| Error::UnknownFlavor {
+                        flavor: value.clone(),
+                    })?;
+                ctx.insert("flavor", flavor);
+            } else {
+                ctx.insert(key, value);
+            }
+        }
+        let result = tera.render(template_name, ctx).map_err(Error::Render)?;
+        let filename =
+            tera::Tera::one_off(filename_template, ctx, false).map_err(Error::Filename)?;
+        Ok(File {
+            combination: combination.clone(),
+            filename: PathBuf::from(filename),
+            contents: output.encode(&result),
+        })
+    };
+
+    let render = |ctx: &mut tera::Context,
+                  index: usize,
+                  combination: &matrix::Combination|
+     -> Result<File, Error> {
+        ctx.insert(
+            matrix::RESERVED_KEY,
+            &serde_json::json!({
+                "index": index + 1,
+                "index0": index,
+                "first": index == 0,
+                "last": index + 1 == combinations.len(),
+                "length": combinations.len(),
+            }),
+        );
+        // include entries can add keys to some combinations only, so they're
+        // removed again before this thread's next combination.
+        let previous = combination
+            .iter()
+            .map(|(key, _)| (key.clone(), ctx.get(key).cloned()))
+            .collect::<Vec<_>>();
+        let file = render_combination(ctx, combination);
+        for (key, value) in previous {
+            match value {
+                Some(value) => ctx.insert(key, &value),
+                None => {
+                    ctx.remove(&key);
+                }
+            }
+        }
+        file
+    };
+
+    // results are collected in matrix order, so the error doesn't depend on
+    // which combination happened to finish rendering first.
+    combinations
+        .iter()
+        .zip(render_parallel(&combinations, ctx, jobs, render))
+        .map(|(combination, rendered)| {
+            rendered.map_err(|e| Error::Combination {
+                combination: combination
+                    .iter()
+                    .map(|(key, value)| format!("{key}={value}"))
+                    .join(", "),
+                source: Box::new(e),
+            })
+        })
+        .collect()
+}
+
+/// Call `render` for every item and its index on up to `jobs` threads,
+/// returning the results in the same order as `items`.
+///
+/// Each thread works on its own copy of `ctx`, which `render` may modify. A
+/// single job runs on the calling thread, which is the only one on wasm.
+fn render_parallel<T, R, F>(items: &[T], ctx: &tera::Context, jobs: usize, render: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&mut tera::Context, usize, &T) -> R + Sync,
+{
+    if jobs <= 1 || items.len() <= 1 {
+        let mut ctx = ctx.clone();
+        return items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| render(&mut ctx, i, item))
+            .collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut results = std::thread::scope(|scope| {
+        let workers = (0..jobs.clamp(1, items.len().max(1)))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut ctx = ctx.clone();
+                    let mut results = vec![];
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(i) else {
+                            break results;
+                        };
+                        results.push((i, render(&mut ctx, i, item)));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|worker| match worker.join() {
+                Ok(results) => results,
+                Err(panic) => std::panic::resume_unwind(panic),
+            })
+            .collect::<Vec<_>>()
+    });
+    results.sort_unstable_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MATRIX: &str = r#"---
+whiskers:
+  version: "2"
+  matrix:
+    - flavor
+    - variant: [a, b]
+  filename: "{{ flavor.identifier }}-{{ variant }}.txt"
+---
+{{ flavor.name }} {{ variant }}"#;
+
+    fn files(result: Rendered) -> Vec<(String, String)> {
+        let Output::Multi(files) = result.output else {
+            panic!("template has a matrix");
+        };
+        files
+            .into_iter()
+            .map(|file| {
+                (
+                    file.filename.display().to_string(),
+                    String::from_utf8(file.contents).expect("output is UTF-8"),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn renders_matrix_in_memory() {
+        let mut renderer = Renderer::new(Options::default());
+        let result = renderer
+            .render(&RenderRequest::new(MATRIX))
+            .expect("template renders");
+        assert!(!result.missing_version);
+        let files = files(result);
+        assert_eq!(files.len(), 8);
+        assert_eq!(files[0], ("latte-a.txt".to_string(), "Latte a".to_string()));
+    }
+
+    #[test]
+    fn single_job_matches_parallel() {
+        let render = |jobs| {
+            let mut renderer = Renderer::new(Options {
+                jobs: NonZeroUsize::new(jobs),
+                ..Options::default()
+            });
+            files(
+                renderer
+                    .render(&RenderRequest::new(MATRIX))
+                    .expect("template renders"),
+            )
+        };
+        assert_eq!(render(1), render(4));
+    }
+
+    #[test]
+    fn overrides_replace_matrix_iterables() {
+        let mut renderer = Renderer::new(Options {
+            overrides: Some(HashMap::from([("variant".to_string(), "b".into())])),
+            ..Options::default()
+        });
+        let result = renderer
+            .render(&RenderRequest::new(MATRIX))
+            .expect("template renders");
+        let files = files(result);
+        assert_eq!(files.len(), 4);
+        assert!(files.iter().all(|(name, _)| name.ends_with("-b.txt")));
+    }
+
+    #[test]
+    fn version_is_checked() {
+        let mut renderer = Renderer::new(Options::default());
+        let request = RenderRequest {
+            check_version: true,
+            ..RenderRequest::new("---\nwhiskers:\n  version: \"^1\"\n---\n")
+        };
+        assert!(matches!(
+            renderer.render(&request),
+            Err(Error::IncompatibleVersion { .. })
+        ));
+
+        let request = RenderRequest {
+            check_version: true,
+            ..RenderRequest::new("hello")
+        };
+        let result = renderer.render(&request).expect("template renders");
+        assert!(result.missing_version);
+    }
+}
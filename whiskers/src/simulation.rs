@@ -0,0 +1,70 @@
+//! color vision deficiency simulation.
+//!
+//! dichromacies use the matrices from Machado, Oliveira & Fernandes (2009),
+//! "A Physiologically-based Model for Simulation of Color Vision Deficiency",
+//! applied in linear RGB.
+
+use crate::colorspace::{linear_to_srgb, srgb_to_linear};
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Deficiency {
+    /// No red cones
+    Protanopia,
+    /// No green cones
+    Deuteranopia,
+    /// No blue cones
+    Tritanopia,
+    /// No color vision at all
+    Achromatopsia,
+}
+
+const PROTANOPIA: [[f64; 3]; 3] = [
+    [0.152_286, 1.052_583, -0.204_868],
+    [0.114_503, 0.786_281, 0.099_216],
+    [-0.003_882, -0.048_116, 1.051_998],
+];
+
+const DEUTERANOPIA: [[f64; 3]; 3] = [
+    [0.367_322, 0.860_646, -0.227_968],
+    [0.280_085, 0.672_501, 0.047_413],
+    [-0.011_820, 0.042_940, 0.968_881],
+];
+
+const TRITANOPIA: [[f64; 3]; 3] = [
+    [1.255_528, -0.076_749, -0.178_779],
+    [-0.078_411, 0.930_809, 0.147_602],
+    [0.004_733, 0.691_367, 0.303_900],
+];
+
+/// achromats only perceive luminance.
+const ACHROMATOPSIA: [[f64; 3]; 3] = [[0.2126, 0.7152, 0.0722]; 3];
+
+impl Deficiency {
+    const fn matrix(self) -> &'static [[f64; 3]; 3] {
+        match self {
+            Self::Protanopia => &PROTANOPIA,
+            Self::Deuteranopia => &DEUTERANOPIA,
+            Self::Tritanopia => &TRITANOPIA,
+            Self::Achromatopsia => &ACHROMATOPSIA,
+        }
+    }
+}
+
+/// simulate how an sRGB color appears with the given deficiency.
+///
+/// `severity` ranges from `0.0` (normal vision) to `1.0` (full dichromacy or
+/// achromatopsia); values in between are interpolated in linear RGB.
+#[must_use]
+pub fn simulate(rgb: [u8; 3], deficiency: Deficiency, severity: f64) -> [u8; 3] {
+    let severity = severity.clamp(0.0, 1.0);
+    let linear = rgb.map(srgb_to_linear);
+    let matrix = deficiency.matrix();
+    std::array::from_fn(|i| {
+        let row = matrix[i];
+        let simulated = row[2].mul_add(linear[2], row[1].mul_add(linear[1], row[0] * linear[0]));
+        linear_to_srgb((simulated - linear[i]).mul_add(severity, linear[i]))
+    })
+}
@@ -1,3 +1,5 @@
+use std::path::{Path, PathBuf};
+
 use indexmap::IndexMap;
 
 use crate::{filters, functions};
@@ -50,15 +52,54 @@ pub fn make_engine() -> tera::Tera {
     tera.register_filter("urlencode_lzma", filters::urlencode_lzma);
     tera.register_filter("trunc", filters::trunc);
     tera.register_filter("mix", filters::mix);
+    tera.register_filter("simulate", filters::simulate);
+    tera.register_filter("format", filters::format);
     tera.register_function("if", functions::if_fn);
     tera.register_function("object", functions::object);
     tera.register_function("css_rgb", functions::css_rgb);
     tera.register_function("css_rgba", functions::css_rgba);
     tera.register_function("css_hsl", functions::css_hsl);
     tera.register_function("css_hsla", functions::css_hsla);
+    tera.register_function("contrast_ratio", functions::contrast_ratio);
+    tera.register_function("apca", functions::apca);
+    tera.register_function("readable_on", functions::readable_on);
     tera
 }
 
+/// Load every file under `dir` into the engine as a partial.
+///
+/// Partials are named by their path relative to `dir`, which lets templates
+/// `include`, `import` and `extend` them, e.g. `{% import "macros.tera" as macros %}`.
+pub fn add_partials(tera: &mut tera::Tera, dir: &Path) -> Result<(), tera::Error> {
+    let mut files = vec![];
+    collect_files(dir, &mut files)
+        .map_err(|e| tera::Error::chain(format!("Failed to read {}", dir.display()), e))?;
+
+    let files = files.into_iter().map(|path| {
+        let name = path
+            .strip_prefix(dir)
+            .unwrap_or(&path)
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        (path, Some(name))
+    });
+    tera.add_template_files(files)
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
 #[must_use]
 pub fn all_functions() -> Vec<Function> {
     vec![
@@ -99,6 +140,28 @@ pub fn all_functions() -> Vec<Function> {
             description: "Convert a color to an HSLA CSS string".to_string(),
             examples: vec![function_example!(css_hsla(color=red) => "hsla(347, 87%, 44%, 1.00)")],
         },
+        Function {
+            name: "contrast_ratio".to_string(),
+            description: "Calculate the WCAG 2 contrast ratio of color `a` on color `b`"
+                .to_string(),
+            examples: vec![function_example!(contrast_ratio(a=text, b=base) => "7.062")],
+        },
+        Function {
+            name: "apca".to_string(),
+            description: "Calculate the APCA lightness contrast (Lc) of text color `fg` on background color `bg`"
+                .to_string(),
+            examples: vec![function_example!(apca(fg=text, bg=base) => "79.275")],
+        },
+        Function {
+            name: "readable_on".to_string(),
+            description: "Pick the most readable of the `candidates` on background color `bg`"
+                .to_string(),
+            examples: vec![
+                function_example!(readable_on(bg=red, candidates=[base, text]) => "#eff1f5"),
+                function_example!(readable_on(bg=base, candidates=[overlay0, text], min=4.5) => "#4c4f69"),
+                function_example!(readable_on(bg=base, candidates=[overlay0, text], algorithm="apca", min=60) => "#4c4f69"),
+            ],
+        },
     ]
 }
 
@@ -111,6 +174,7 @@ pub fn all_filters() -> Vec<Filter> {
             examples: vec![
                 filter_example!(red | add(hue=30) => "#ff6666"),
                 filter_example!(red | add(saturation=0.5) => "#ff6666"),
+                filter_example!(red | add(lightness=10, space="oklch") => "#f74256"),
             ],
         },
         Filter {
@@ -119,6 +183,7 @@ pub fn all_filters() -> Vec<Filter> {
             examples: vec![
                 filter_example!(red | sub(hue=30) => "#d30f9b"),
                 filter_example!(red | sub(saturation=60) => "#8f5360"),
+                filter_example!(red | sub(chroma=0.1, space="oklch") => "#ab5355"),
             ],
         },
         Filter {
@@ -127,12 +192,33 @@ pub fn all_filters() -> Vec<Filter> {
             examples: vec![
                 filter_example!(red | mod(lightness=80) => "#f8a0b3"),
                 filter_example!(red | mod(opacity=0.5) => "#d20f3980"),
+                filter_example!(red | mod(lightness=80, space="oklch") => "#ff9f9f"),
             ],
         },
         Filter {
             name: "mix".to_string(),
             description: "Mix two colors together".to_string(),
-            examples: vec![filter_example!(red | mix(color=base, amount=0.5) => "#e08097")],
+            examples: vec![
+                filter_example!(red | mix(color=base, amount=0.5) => "#e08097"),
+                filter_example!(red | mix(color=base, amount=0.5, space="oklab") => "#eb9395"),
+            ],
+        },
+        Filter {
+            name: "simulate".to_string(),
+            description: "Simulate how a color appears with a color vision deficiency".to_string(),
+            examples: vec![
+                filter_example!(red | simulate(deficiency="deuteranopia") => "#847732"),
+                filter_example!(red | simulate(deficiency="protanopia", severity=0.5) => "#a43b39"),
+            ],
+        },
+        Filter {
+            name: "format".to_string(),
+            description: "Format a color with a named preset or a pattern".to_string(),
+            examples: vec![
+                filter_example!(red | format(preset="argb_int") => "0xFFD20F39"),
+                filter_example!(red | format(preset="float_rgb") => "0.824 0.059 0.224"),
+                filter_example!(red | format(pattern="rgb:{r:x16}/{g:x16}/{b:x16}") => "rgb:d2d2/0f0f/3939"),
+            ],
         },
         Filter {
             name: "urlencode_lzma".to_string(),
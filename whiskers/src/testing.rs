@@ -0,0 +1,304 @@
+//! tests that templates declare in their `whiskers.tests` frontmatter
+//! section, so that ports can carry their own assertions.
+//!
+//! Each case renders the template with the same engine as a normal render,
+//! for a flavor and matrix values of its choosing, and checks every output
+//! against the expected substrings, regular expressions and snapshot file.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use indexmap::IndexMap;
+use itertools::Itertools as _;
+
+use crate::{
+    check,
+    diagnostic::Diagnostic,
+    encoding, frontmatter,
+    render::{self, FrontmatterOptions, Output, RenderRequest, Renderer},
+};
+
+/// A test case from the `whiskers.tests` frontmatter section.
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Case {
+    /// Name of the case in reports. Defaults to its flavor and matrix values.
+    pub name: Option<String>,
+    /// Render a single flavor, like `--flavor`.
+    pub flavor: Option<String>,
+    /// Values of matrix iterables to render, which replace the lists in the
+    /// matrix like overrides do.
+    #[serde(default)]
+    pub matrix: IndexMap<String, String>,
+    /// Substrings that every output must contain.
+    #[serde(default)]
+    pub contains: OneOrMany,
+    /// Regular expressions that every output must match, in which `^` and
+    /// `$` match at the start and end of lines.
+    #[serde(default)]
+    pub matches: OneOrMany,
+    /// File, relative to the template, that the case's single output must be
+    /// identical to.
+    pub snapshot: Option<PathBuf>,
+}
+
+/// A string, or a list of them.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(from = "OneOrManyRepr")]
+pub struct OneOrMany(pub Vec<String>);
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum OneOrManyRepr {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl From<OneOrManyRepr> for OneOrMany {
+    fn from(repr: OneOrManyRepr) -> Self {
+        match repr {
+            OneOrManyRepr::One(value) => Self(vec![value]),
+            OneOrManyRepr::Many(values) => Self(values),
+        }
+    }
+}
+
+/// The result of a test case.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct Outcome {
+    pub name: String,
+    /// Why the case failed, which is empty if it passed.
+    pub failures: Vec<String>,
+}
+
+impl Outcome {
+    #[must_use]
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Frontmatter is invalid")]
+    Frontmatter(#[from] frontmatter::Error),
+
+    #[error("Frontmatter `whiskers` section is invalid")]
+    InvalidOptions(#[source] serde_json::Error),
+
+    #[error("Snapshot {path} could not be read")]
+    Snapshot {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+/// The test cases in the `whiskers` section, which holds the other options
+/// too.
+#[derive(serde::Deserialize)]
+pub(crate) struct Tests {
+    #[serde(default)]
+    pub tests: Vec<Case>,
+}
+
+/// Run the test cases declared by a template, in declaration order.
+///
+/// Cases render with `options`, except for the flavor and matrix values that
+/// they set. Render errors fail the case rather than returning an error.
+pub fn run(request: &RenderRequest, options: &render::Options) -> Result<Vec<Outcome>, Error> {
+    let doc = frontmatter::parse(&request.source)?;
+    let Some(section) = doc.frontmatter.get("whiskers") else {
+        return Ok(vec![]);
+    };
+    let spec: Tests = tera::from_value(section.clone()).map_err(Error::InvalidOptions)?;
+    let output = tera::from_value::<FrontmatterOptions>(section.clone())
+        .map_err(Error::InvalidOptions)?
+        .output();
+
+    spec.tests
+        .iter()
+        .enumerate()
+        .map(|(index, case)| {
+            let name = case.name.clone().unwrap_or_else(|| {
+                let inputs = case
+                    .flavor
+                    .iter()
+                    .map(|flavor| format!("flavor={flavor}"))
+                    .chain(
+                        case.matrix
+                            .iter()
+                            .map(|(key, value)| format!("{key}={value}")),
+                    )
+                    .join(", ");
+                if inputs.is_empty() {
+                    format!("case {}", index + 1)
+                } else {
+                    inputs
+                }
+            });
+            let failures = run_case(case, request, options, output)?;
+            Ok(Outcome { name, failures })
+        })
+        .collect()
+}
+
+fn run_case(
+    case: &Case,
+    request: &RenderRequest,
+    options: &render::Options,
+    output: encoding::Output,
+) -> Result<Vec<String>, Error> {
+    let mut overrides = options.overrides.clone().unwrap_or_default();
+    overrides.extend(
+        case.matrix
+            .iter()
+            .map(|(key, value)| (key.clone(), tera::Value::String(value.clone()))),
+    );
+    let mut renderer = Renderer::new(render::Options {
+        flavor: case.flavor.clone().or_else(|| options.flavor.clone()),
+        overrides: Some(overrides).filter(|overrides: &HashMap<_, _>| !overrides.is_empty()),
+        ..options.clone()
+    });
+
+    let outputs = match renderer.render(request) {
+        Ok(result) => match result.output {
+            Output::Single(contents) => vec![(None, contents)],
+            Output::Multi(files) => files
+                .into_iter()
+                .map(|file| (Some(file.filename), file.contents))
+                .collect(),
+        },
+        Err(e) => {
+            let name = request
+                .path
+                .as_ref()
+                .map_or_else(|| request.name.clone(), |path| path.display().to_string());
+            return Ok(vec![Diagnostic::new(&e, &request.source, &name).to_string()]);
+        }
+    };
+    if outputs.is_empty() {
+        return Ok(vec!["Matrix has no combinations to render".to_string()]);
+    }
+
+    let mut failures = vec![];
+    let mut patterns = vec![];
+    for pattern in &case.matches.0 {
+        match regex::RegexBuilder::new(pattern).multi_line(true).build() {
+            Ok(regex) => patterns.push(regex),
+            Err(e) => failures.push(format!("Pattern `{pattern}` is invalid: {e}")),
+        }
+    }
+    for (filename, contents) in &outputs {
+        let text = output.decode(contents);
+        let subject = filename
+            .as_ref()
+            .map_or_else(|| "Output".to_string(), |path| path.display().to_string());
+        for substring in &case.contains.0 {
+            if !text.contains(substring.as_str()) {
+                failures.push(format!("{subject} doesn't contain `{substring}`"));
+            }
+        }
+        for regex in &patterns {
+            if !regex.is_match(&text) {
+                failures.push(format!("{subject} doesn't match `{regex}`"));
+            }
+        }
+    }
+
+    if let Some(ref snapshot) = case.snapshot {
+        // snapshots are relative to the template itself
+        let path = request
+            .path
+            .as_deref()
+            .and_then(|path| path.parent())
+            .map_or_else(|| snapshot.clone(), |parent| parent.join(snapshot));
+        match outputs.as_slice() {
+            [(_, actual)] => match std::fs::read(&path) {
+                Ok(expected) if expected == *actual => {}
+                Ok(expected) => failures.push(format!(
+                    "Output doesn't match snapshot {}\n{}",
+                    path.display(),
+                    check::unified_diff(&expected, actual, &path, false).trim_end()
+                )),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    failures.push(format!("Snapshot {} doesn't exist", path.display()));
+                }
+                Err(e) => return Err(Error::Snapshot { path, source: e }),
+            },
+            outputs => failures.push(format!(
+                "Snapshot needs a case with a single output, but it rendered {}",
+                outputs.len()
+            )),
+        }
+    }
+
+    Ok(failures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MATRIX: &str = r#"---
+whiskers:
+  version: "2"
+  matrix:
+    - flavor
+    - accent
+  filename: "{{ flavor.identifier }}-{{ accent }}.txt"
+  tests:
+    - flavor: mocha
+      matrix:
+        accent: mauve
+      contains: "accent: cba6f7"
+      matches: ['^mocha \w+']
+    - name: every accent
+      flavor: latte
+      contains: [latte, "accent: ffffff"]
+---
+{{ flavor.identifier }} {{ accent }}
+accent: {{ flavor.colors[accent].hex }}
+"#;
+
+    #[test]
+    fn runs_cases() {
+        let outcomes =
+            run(&RenderRequest::new(MATRIX), &render::Options::default()).expect("tests can run");
+        assert_eq!(outcomes.len(), 2);
+
+        assert_eq!(outcomes[0].name, "flavor=mocha, accent=mauve");
+        assert!(outcomes[0].passed(), "{:?}", outcomes[0].failures);
+
+        assert_eq!(outcomes[1].name, "every accent");
+        assert_eq!(outcomes[1].failures.len(), 14);
+        assert_eq!(
+            outcomes[1].failures[0],
+            "latte-rosewater.txt doesn't contain `accent: ffffff`"
+        );
+    }
+
+    #[test]
+    fn render_errors_fail_the_case() {
+        let source = "---\nwhiskers:\n  tests:\n    - contains: x\n---\n{{ missing }}";
+        let outcomes =
+            run(&RenderRequest::new(source), &render::Options::default()).expect("tests can run");
+        assert_eq!(outcomes[0].name, "case 1");
+        assert!(outcomes[0].failures[0]
+            .starts_with("Template render failed: Variable `missing` not found"));
+    }
+
+    #[test]
+    fn snapshots() {
+        let dir = tempfile::tempdir().expect("tempdir can be created");
+        std::fs::write(dir.path().join("mocha.txt"), "1e1e2e\n").expect("snapshot is written");
+        let source = "---\nwhiskers:\n  tests:\n    - flavor: mocha\n      snapshot: mocha.txt\n    - flavor: latte\n      snapshot: mocha.txt\n    - flavor: mocha\n      snapshot: missing.txt\n---\n{{ flavor.colors.base.hex }}\n";
+        let request = RenderRequest {
+            path: Some(dir.path().join("theme.tera")),
+            ..RenderRequest::new(source)
+        };
+        let outcomes = run(&request, &render::Options::default()).expect("tests can run");
+        assert!(outcomes[0].passed(), "{:?}", outcomes[0].failures);
+        assert!(outcomes[1].failures[0].starts_with("Output doesn't match snapshot"));
+        assert!(outcomes[2].failures[0].ends_with("missing.txt doesn't exist"));
+    }
+}
@@ -0,0 +1,377 @@
+//! checks of a template without rendering it, which locate every problem
+//! within the template.
+//!
+//! The `whiskers` frontmatter section is checked against the same options that
+//! rendering reads, so that each problem can be located within it. Unlike
+//! rendering, unknown keys are reported, since they're usually typos.
+
+use std::{collections::HashMap, fmt, path::Path};
+
+use crate::{
+    diagnostic::{describe, error_location, Locator},
+    frontmatter, matrix, models,
+    render::{self, FrontmatterOptions},
+    testing,
+};
+
+/// A problem with a template, at a line and column counting from one.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct Problem {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+/// The frontmatter, with only the `whiskers` section checked.
+#[derive(serde::Deserialize)]
+struct Frontmatter<T> {
+    whiskers: Option<T>,
+}
+
+/// An element of the matrix, checked while it is deserialized so that the
+/// error points at the element.
+struct MatrixElement;
+
+impl<'de> serde::Deserialize<'de> for MatrixElement {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(MatrixElementVisitor)
+    }
+}
+
+struct MatrixElementVisitor;
+
+impl<'de> serde::de::Visitor<'de> for MatrixElementVisitor {
+    type Value = MatrixElement;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("the name of a magic iterable, or an object with a single key")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        if matrix::MAGIC_ITERABLES.contains(&v) {
+            Ok(MatrixElement)
+        } else {
+            Err(E::unknown_variant(v, &matrix::MAGIC_ITERABLES))
+        }
+    }
+
+    fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        use serde::de::Error as _;
+
+        let Some(key) = map.next_key::<String>()? else {
+            return Err(A::Error::invalid_length(0, &self));
+        };
+        if key == "exclude" || key == "include" {
+            let rules: Vec<HashMap<String, String>> = map.next_value()?;
+            if rules.iter().any(HashMap::is_empty) {
+                return Err(A::Error::custom(format!(
+                    "matrix {key} entries must not be empty"
+                )));
+            }
+        } else {
+            map.next_value::<Vec<String>>()?;
+        }
+        if map.next_key::<String>()?.is_some() {
+            return Err(A::Error::invalid_length(2, &self));
+        }
+        Ok(MatrixElement)
+    }
+}
+
+/// Check the frontmatter, matrix, filename template and template syntax,
+/// returning every problem found.
+///
+/// `path` is the path of the template, which frontmatter partials are relative
+/// to. Errors that aren't caused by the template, such as partials from
+/// `options` that can't be loaded, are returned as an error instead.
+pub fn validate(
+    source: &str,
+    path: Option<&Path>,
+    options: &render::Options,
+) -> Result<Vec<Problem>, render::Error> {
+    let locator = Locator::new(source);
+    let mut problems = vec![];
+
+    if let Some((frontmatter, _)) = frontmatter::split(source) {
+        let parsed =
+            serde_yaml::from_str::<Frontmatter<FrontmatterOptions<MatrixElement>>>(frontmatter)
+                .and_then(|parsed| {
+                    serde_yaml::from_str::<Frontmatter<testing::Tests>>(frontmatter)
+                        .map(|_| parsed.whiskers)
+                });
+        let whiskers = match parsed {
+            Ok(whiskers) => whiskers,
+            Err(e) => {
+                let (line, column) = e.location().map_or((1, 1), |l| (l.line(), l.column()));
+                let suffix = format!(" at line {line} column {column}");
+                let message = e.to_string();
+                return Ok(vec![Problem {
+                    line: locator.frontmatter_line + line,
+                    column,
+                    message: message
+                        .strip_suffix(&suffix)
+                        .unwrap_or(&message)
+                        .to_string(),
+                }]);
+            }
+        };
+        if let Some(whiskers) = whiskers {
+            problems.extend(unknown_keys(source, &locator));
+            problems.extend(check_matrix(source, &whiskers, options, &locator));
+            if let Some(ref filename) = whiskers.filename {
+                if let Err(e) = tera::Tera::default().add_raw_template("filename", filename) {
+                    problems.push(problem_at(&locator, "filename", describe(&e)));
+                }
+            }
+        }
+    }
+    if !problems.is_empty() {
+        return Ok(problems);
+    }
+
+    let request = render::RenderRequest {
+        path: path.map(Path::to_path_buf),
+        check_version: true,
+        ..render::RenderRequest::new(source)
+    };
+    match render::validate(&request, options) {
+        Ok(()) => {}
+        Err(ref err @ render::Error::InvalidTemplate(ref e)) => {
+            let (line, column) = error_location(e).unwrap_or((1, 1));
+            problems.push(Problem {
+                line: locator.body_line + line,
+                column,
+                message: describe(err),
+            });
+        }
+        Err(ref e @ render::Error::IncompatibleVersion { .. }) => {
+            problems.push(problem_at(&locator, "version", describe(e)));
+        }
+        Err(ref e @ render::Error::FrontmatterPartials(_)) => {
+            problems.push(problem_at(&locator, "partials", describe(e)));
+        }
+        Err(e) => return Err(e),
+    }
+    Ok(problems)
+}
+
+/// Problems for the keys in the `whiskers` section that no option reads,
+/// which rendering ignores.
+fn unknown_keys(source: &str, locator: &Locator) -> Vec<Problem> {
+    let known = [
+        field_names::<FrontmatterOptions>(),
+        field_names::<testing::Tests>(),
+    ]
+    .concat();
+    let Some(section) = frontmatter::parse(source)
+        .ok()
+        .and_then(|doc| doc.frontmatter.get("whiskers")?.as_object().cloned())
+    else {
+        return vec![];
+    };
+    let expected = known
+        .iter()
+        .map(|key| format!("`{key}`"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut problems = section
+        .keys()
+        .filter(|key| !known.contains(&key.as_str()))
+        .map(|key| {
+            problem_at(
+                locator,
+                key,
+                format!("whiskers: unknown field `{key}`, expected one of {expected}"),
+            )
+        })
+        .collect::<Vec<_>>();
+    problems.sort_by_key(|problem| (problem.line, problem.column));
+    problems
+}
+
+/// The fields of a struct that derives `Deserialize`, which serde passes to
+/// `Deserializer::deserialize_struct`.
+fn field_names<'de, T: serde::Deserialize<'de>>() -> &'static [&'static str] {
+    struct Fields<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> serde::Deserializer<'de> for Fields<'_> {
+        type Error = serde::de::value::Error;
+
+        fn deserialize_any<V: serde::de::Visitor<'de>>(
+            self,
+            _: V,
+        ) -> Result<V::Value, Self::Error> {
+            Err(serde::de::Error::custom("expected a struct"))
+        }
+
+        fn deserialize_struct<V: serde::de::Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(serde::de::Error::custom("fields are recorded"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(Fields(&mut fields));
+    fields
+}
+
+/// A problem at `key` in the `whiskers` section.
+fn problem_at(locator: &Locator, key: &str, message: String) -> Problem {
+    let (line, column) = locator.key_position(key);
+    Problem {
+        line,
+        column,
+        message,
+    }
+}
+
+/// Check what can't be checked while deserializing the matrix: the rules must
+/// refer to iterables in the matrix, and a filename template is required.
+fn check_matrix(
+    source: &str,
+    whiskers: &FrontmatterOptions<MatrixElement>,
+    options: &render::Options,
+    locator: &Locator,
+) -> Option<Problem> {
+    whiskers.matrix.as_ref()?;
+    if whiskers.filename.is_none() {
+        return Some(problem_at(
+            locator,
+            "matrix",
+            render::Error::MissingFilename.to_string(),
+        ));
+    }
+    let values = frontmatter::parse(source)
+        .ok()?
+        .frontmatter
+        .get("whiskers")?
+        .get("matrix")?
+        .as_array()?
+        .clone();
+    let flavors = models::flavor_identifiers(options.palette.as_ref());
+    matrix::from_values(values, flavors)
+        .err()
+        .map(|e| problem_at(locator, "matrix", e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(source: &str) -> Vec<Problem> {
+        validate(source, None, &render::Options::default()).expect("template can be validated")
+    }
+
+    #[test]
+    fn valid_template() {
+        let source = "---\nwhiskers:\n  version: \"2\"\n  matrix:\n    - flavor\n    - variant: [a, b]\n  filename: \"{{ flavor.identifier }}-{{ variant }}.txt\"\n---\n{{ red.hex }}\n";
+        assert_eq!(problems(source), []);
+        assert_eq!(problems("{{ red.hex }}"), []);
+    }
+
+    #[test]
+    fn unknown_key() {
+        let source = "---\napp: helix\nwhiskers:\n  version: \"2\"\n  hex_prefx: \"#\"\n---\n";
+        let problems = problems(source);
+        assert_eq!(problems.len(), 1);
+        assert_eq!((problems[0].line, problems[0].column), (5, 3));
+        assert!(problems[0]
+            .message
+            .starts_with("whiskers: unknown field `hex_prefx`, expected one of"));
+    }
+
+    #[test]
+    fn invalid_types() {
+        let source = "---\nwhiskers:\n  version: \"2\"\n  capitalize_hex: \"yes\"\n---\n";
+        assert_eq!(
+            problems(source),
+            [Problem {
+                line: 4,
+                column: 19,
+                message:
+                    "whiskers.capitalize_hex: invalid type: string \"yes\", expected a boolean"
+                        .to_string(),
+            }]
+        );
+
+        let source = "---\nwhiskers:\n  version: \"2\"\n  matrix:\n    - flavor\n    - accents\n  filename: x\n---\n";
+        let problems = super::tests::problems(source);
+        assert_eq!((problems[0].line, problems[0].column), (6, 7));
+        assert!(problems[0]
+            .message
+            .contains("unknown variant `accents`, expected `flavor` or `accent`"));
+    }
+
+    #[test]
+    fn matrix_problems() {
+        let source = "---\nwhiskers:\n  version: \"2\"\n  matrix:\n    - flavor\n---\n";
+        assert_eq!(
+            problems(source),
+            [Problem {
+                line: 4,
+                column: 3,
+                message: "Filename template is required for multi-output render".to_string(),
+            }]
+        );
+
+        let source = "---\nwhiskers:\n  version: \"2\"\n  matrix:\n    - flavor\n    - exclude: [{ accent: red }]\n  filename: x\n---\n";
+        let problems = super::tests::problems(source);
+        assert_eq!((problems[0].line, problems[0].column), (4, 3));
+        assert!(problems[0].message.contains("refers to accent"));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let source = "---\nwhiskers:\n  version: \"2\"\n  matrix:\n    - flavor\n  filename: \"{{ flavor.\"\n---\nbody\n";
+        let problems = super::tests::problems(source);
+        assert_eq!((problems[0].line, problems[0].column), (6, 3));
+
+        let source = "---\nwhiskers:\n  version: \"2\"\n---\nline\n{{ red. }}\n";
+        let problems = super::tests::problems(source);
+        assert_eq!((problems[0].line, problems[0].column), (6, 7));
+        assert!(problems[0].message.starts_with("Template is invalid"));
+    }
+
+    #[test]
+    fn incompatible_version() {
+        let source = "---\nwhiskers:\n  hex_prefix: \"#\"\n  version: \"^1\"\n---\n";
+        let problems = problems(source);
+        assert_eq!((problems[0].line, problems[0].column), (4, 3));
+    }
+
+    #[test]
+    fn schema_matches_frontmatter_options() {
+        let schema: serde_json::Value =
+            serde_json::from_str(include_str!("../schema/frontmatter.schema.json"))
+                .expect("schema is valid JSON");
+        let mut documented = schema["definitions"]["whiskers"]["properties"]
+            .as_object()
+            .expect("schema has whiskers properties")
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut accepted = [
+            field_names::<FrontmatterOptions>(),
+            field_names::<testing::Tests>(),
+        ]
+        .concat()
+        .into_iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+
+        documented.sort();
+        accepted.sort();
+        assert_eq!(documented, accepted);
+    }
+}
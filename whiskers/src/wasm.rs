@@ -0,0 +1,198 @@
+// needed for WASM
+#![allow(clippy::needless_pass_by_value)]
+
+//! bindings for rendering templates in the browser. There's no filesystem, so
+//! the palette and overrides are passed in rather than read from files.
+
+use std::collections::HashMap;
+
+use indexmap::IndexMap;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    models::{ColorOverrides, CustomPalette},
+    render::{self, RenderRequest, Renderer},
+    simulation::Deficiency,
+};
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+struct Options {
+    flavor: Option<String>,
+    palette: Option<CustomPalette>,
+    color_overrides: Option<ColorOverrides>,
+    overrides: Option<HashMap<String, tera::Value>>,
+    simulate: Option<Deficiency>,
+    hex_prefix: Option<String>,
+    capitalize_hex: Option<bool>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+enum Rendered {
+    #[serde(rename_all = "camelCase")]
+    Single {
+        missing_version: bool,
+        #[serde(flatten)]
+        contents: Contents,
+    },
+    #[serde(rename_all = "camelCase")]
+    Multi {
+        missing_version: bool,
+        files: Vec<File>,
+    },
+}
+
+#[derive(serde::Serialize)]
+struct File {
+    combination: IndexMap<String, String>,
+    filename: String,
+    #[serde(flatten)]
+    contents: Contents,
+}
+
+/// Rendered output as a `Uint8Array`, plus the decoded text when the output
+/// encoding is UTF-8.
+#[derive(serde::Serialize)]
+struct Contents {
+    text: Option<String>,
+    #[serde(serialize_with = "as_uint8_array")]
+    bytes: Vec<u8>,
+}
+
+impl From<Vec<u8>> for Contents {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self {
+            text: String::from_utf8(bytes.clone()).ok(),
+            bytes,
+        }
+    }
+}
+
+fn as_uint8_array<S: serde::Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_bytes(bytes)
+}
+
+/// An error with the chain of errors that caused it, and the matrix
+/// combination that failed to render, if any.
+#[derive(serde::Serialize)]
+struct Error {
+    message: String,
+    causes: Vec<String>,
+    combination: Option<String>,
+}
+
+impl Error {
+    fn new(err: &dyn std::error::Error) -> Self {
+        let mut causes = vec![];
+        let mut source = err.source();
+        while let Some(cause) = source {
+            causes.push(cause.to_string());
+            source = cause.source();
+        }
+        Self {
+            message: err.to_string(),
+            causes,
+            combination: None,
+        }
+    }
+}
+
+impl From<render::Error> for Error {
+    fn from(err: render::Error) -> Self {
+        let combination = match err {
+            render::Error::Combination {
+                ref combination, ..
+            } => Some(combination.clone()),
+            _ => None,
+        };
+        Self {
+            combination,
+            ..Self::new(&err)
+        }
+    }
+}
+
+impl From<Error> for JsValue {
+    fn from(err: Error) -> Self {
+        to_js(&err).unwrap_or_else(|e| e)
+    }
+}
+
+fn to_js<T: serde::Serialize>(value: &T) -> Result<JsValue, JsValue> {
+    value
+        .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .map_err(JsValue::from)
+}
+
+/// Render a template against the Catppuccin palette or `options.palette`.
+///
+/// `options` may set `flavor`, `palette`, `colorOverrides`, `overrides`,
+/// `simulate`, `hexPrefix` and `capitalizeHex`, which behave like the
+/// command-line arguments of the same names.
+///
+/// Returns `{ kind: "single", missingVersion, text, bytes }` for a template
+/// without a matrix, or `{ kind: "multi", missingVersion, files }` with one
+/// `{ combination, filename, text, bytes }` per matrix combination.
+///
+/// # Errors
+/// Throws `{ message, causes, combination }` if the options are invalid or the
+/// template fails to render.
+#[wasm_bindgen]
+pub fn render(template: &str, options: JsValue) -> Result<JsValue, JsValue> {
+    let options: Options = if options.is_undefined() || options.is_null() {
+        Options::default()
+    } else {
+        serde_wasm_bindgen::from_value(options).map_err(|e| Error {
+            message: "Invalid options".to_string(),
+            causes: vec![e.to_string()],
+            combination: None,
+        })?
+    };
+    if let Some(ref palette) = options.palette {
+        palette.validate().map_err(|e| Error::new(&e))?;
+    }
+
+    let mut renderer = Renderer::new(render::Options {
+        flavor: options.flavor,
+        palette: options.palette,
+        color_overrides: options.color_overrides,
+        overrides: options.overrides,
+        simulate: options.simulate,
+        hex_prefix: options.hex_prefix,
+        capitalize_hex: options.capitalize_hex,
+        ..render::Options::default()
+    });
+    let request = RenderRequest {
+        check_version: true,
+        ..RenderRequest::new(template)
+    };
+    let result = renderer.render(&request).map_err(Error::from)?;
+
+    let missing_version = result.missing_version;
+    let output = match result.output {
+        render::Output::Single(contents) => Rendered::Single {
+            missing_version,
+            contents: contents.into(),
+        },
+        render::Output::Multi(files) => Rendered::Multi {
+            missing_version,
+            files: files
+                .into_iter()
+                .map(|file| File {
+                    combination: file.combination.into_iter().collect(),
+                    filename: file.filename.display().to_string(),
+                    contents: file.contents.into(),
+                })
+                .collect(),
+        },
+    };
+    to_js(&output)
+}
+
+/// The version of whiskers, which templates can require in their frontmatter.
+#[must_use]
+#[wasm_bindgen]
+pub fn version() -> String {
+    env!("CARGO_PKG_VERSION").to_string()
+}
@@ -1,8 +1,31 @@
+#[cfg(test)]
+const ACCENT_MATRIX: &str = r#"---
+whiskers:
+  version: "2"
+  matrix:
+    - accent
+  filename: "{{ accent }}.txt"
+---
+{{ accent }}"#;
+
+#[cfg(test)]
+const PRUNE_MATRIX: &str = r#"---
+whiskers:
+  version: "2"
+  matrix:
+    - variant: [a]
+  filename: "out/{{ variant }}.txt"
+---
+{{ variant }}
+"#;
+
 #[cfg(test)]
 mod happy_path {
     use assert_cmd::Command;
     use predicates::prelude::predicate;
 
+    use super::{ACCENT_MATRIX, PRUNE_MATRIX};
+
     /// Test that the CLI can render a single-flavor template file
     #[test]
     fn test_single() {
@@ -37,6 +60,530 @@ mod happy_path {
         ));
     }
 
+    /// Test that the matrix follows declaration order
+    #[test]
+    fn test_matrix_order() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        let assert = cmd
+            .args(["--dry-run", "tests/fixtures/multifile.tera"])
+            .assert();
+        assert.success().stdout(predicate::str::starts_with(
+            "Would write 46 bytes into catppuccin-latte-rosewater-normal.ini\n\
+             Would write 45 bytes into catppuccin-latte-flamingo-normal.ini\n",
+        ));
+    }
+
+    /// Test that the matrix iteration variables are available
+    #[test]
+    fn test_matrix_variables() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        let assert = cmd
+            .args(["-", "--dry-run"])
+            .write_stdin(
+                r#"---
+whiskers:
+  version: "2"
+  matrix:
+    - variant: [a, b, c]
+  filename: "{{ matrix.index }}-of-{{ matrix.length }}-{{ variant }}{% if matrix.first %}-first{% endif %}{% if matrix.last %}-last{% endif %}.txt"
+---
+{{ matrix.index0 }}"#,
+            )
+            .assert();
+        assert.success().stdout(
+            "Would write 1 bytes into 1-of-3-a-first.txt\n\
+             Would write 1 bytes into 2-of-3-b.txt\n\
+             Would write 1 bytes into 3-of-3-c-last.txt\n",
+        );
+    }
+
+    /// Test that include entries add variables to the combinations they match
+    #[test]
+    fn test_matrix_include_adds_variables() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        let assert = cmd
+            .args(["-", "--dry-run", "--jobs", "1"])
+            .write_stdin(
+                r#"---
+whiskers:
+  version: "2"
+  matrix:
+    - variant: [dark, light]
+    - include:
+        - { variant: dark, suffix: "-night" }
+  filename: "{{ variant }}{{ suffix | default(value='') }}.txt"
+---
+{{ variant }}"#,
+            )
+            .assert();
+        assert.success().stdout(
+            "Would write 4 bytes into dark-night.txt\n\
+             Would write 5 bytes into light.txt\n",
+        );
+    }
+
+    /// Test that overrides narrowing an iterable also drop its includes
+    #[test]
+    fn test_matrix_overrides_with_include() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        let assert = cmd
+            .args(["-", "--dry-run", "--overrides", r#"{"accent": ["red"]}"#])
+            .write_stdin(
+                r#"---
+whiskers:
+  version: "2"
+  matrix:
+    - accent
+    - variant: [flat]
+    - include:
+        - { accent: red, variant: bold }
+        - { accent: blue, variant: bold }
+  filename: "{{ accent }}-{{ variant }}.txt"
+---
+{{ accent }}"#,
+            )
+            .assert();
+        assert.success().stdout(
+            "Would write 3 bytes into red-flat.txt\n\
+             Would write 3 bytes into red-bold.txt\n",
+        );
+    }
+
+    /// Test that parallel rendering keeps the matrix order
+    #[test]
+    fn test_jobs() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        let assert = cmd
+            .args(["-", "--dry-run", "--jobs", "4"])
+            .write_stdin(ACCENT_MATRIX)
+            .assert();
+        let expected = [
+            "rosewater",
+            "flamingo",
+            "pink",
+            "mauve",
+            "red",
+            "maroon",
+            "peach",
+            "yellow",
+            "green",
+            "teal",
+            "sky",
+            "sapphire",
+            "blue",
+            "lavender",
+        ]
+        .map(|accent| format!("Would write {} bytes into {accent}.txt\n", accent.len()))
+        .concat();
+        assert.success().stdout(expected);
+    }
+
+    /// Test that the CLI can write a manifest of a multi-output render
+    #[test]
+    fn test_manifest() {
+        let dir = tempfile::tempdir().expect("tempdir can be created");
+        let manifest = dir.path().join("manifest.json");
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.args(["--dry-run", "tests/fixtures/multifile.tera", "--manifest"])
+            .arg(&manifest)
+            .assert()
+            .success();
+
+        let manifest: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(manifest).expect("manifest is written"))
+                .expect("manifest is valid JSON");
+        assert_eq!(manifest["dry_run"], true);
+        let files = manifest["files"].as_array().expect("files is an array");
+        assert_eq!(files.len(), 2 * 4 * 14);
+        let file = files
+            .iter()
+            .find(|file| file["filename"] == "catppuccin-macchiato-yellow-no-italics.ini")
+            .expect("manifest lists every combination");
+        assert_eq!(file["matrix"]["flavor"], "macchiato");
+        assert_eq!(file["matrix"]["accent"], "yellow");
+        assert_eq!(file["matrix"]["variant"], "no-italics");
+        assert_eq!(file["sha256"].as_str().map(str::len), Some(64));
+    }
+
+    /// Test that check mode doesn't write a manifest of files it didn't write
+    #[test]
+    fn test_manifest_check() {
+        let dir = tempfile::tempdir().expect("tempdir can be created");
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.current_dir(dir.path())
+            .args(["-", "--check", "--manifest", "manifest.json"])
+            .write_stdin(ACCENT_MATRIX)
+            .assert()
+            .failure();
+        assert!(!dir.path().join("manifest.json").exists());
+
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.current_dir(dir.path())
+            .args(["-", "--check", "--update", "--manifest", "manifest.json"])
+            .write_stdin(ACCENT_MATRIX)
+            .assert()
+            .success();
+        assert!(dir.path().join("manifest.json").exists());
+    }
+
+    /// Test that --prune deletes outputs matching the glob that are no longer rendered
+    #[test]
+    fn test_prune() {
+        let dir = tempfile::tempdir().expect("tempdir can be created");
+        std::fs::create_dir(dir.path().join("out")).expect("out dir can be created");
+        std::fs::write(dir.path().join("out/stale.txt"), "").expect("stale file can be written");
+        std::fs::write(dir.path().join("out/keep.cfg"), "").expect("other file can be written");
+
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.current_dir(dir.path())
+            .args(["-", "--prune=out/*.txt"])
+            .write_stdin(PRUNE_MATRIX)
+            .assert()
+            .success();
+
+        assert!(dir.path().join("out/a.txt").exists());
+        assert!(dir.path().join("out/keep.cfg").exists());
+        assert!(!dir.path().join("out/stale.txt").exists());
+    }
+
+    /// Test that --prune without a glob uses the files in the previous manifest,
+    /// and doesn't take the template that follows it as its glob
+    #[test]
+    fn test_prune_manifest() {
+        let dir = tempfile::tempdir().expect("tempdir can be created");
+        let render = |template: &str| {
+            let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+            cmd.current_dir(dir.path())
+                .args(["--manifest", "manifest.json", "--prune", "-"])
+                .write_stdin(template)
+                .assert()
+                .success();
+        };
+
+        render(&PRUNE_MATRIX.replace("[a]", "[a, b]"));
+        assert!(dir.path().join("out/b.txt").exists());
+        render(PRUNE_MATRIX);
+        assert!(dir.path().join("out/a.txt").exists());
+        assert!(!dir.path().join("out/b.txt").exists());
+    }
+
+    /// Test that update mode writes only the files that changed
+    #[test]
+    fn test_update() {
+        let dir = tempfile::tempdir().expect("tempdir can be created");
+        std::fs::create_dir(dir.path().join("out")).expect("out dir can be created");
+        std::fs::write(dir.path().join("out/a.txt"), "a\n").expect("output can be written");
+        std::fs::write(dir.path().join("out/b.txt"), "B\n").expect("output can be written");
+        std::fs::write(dir.path().join("out/stale.txt"), "").expect("stale file can be written");
+
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.current_dir(dir.path())
+            .args(["-", "--check", "--update", "--prune=out/*.txt"])
+            .write_stdin(PRUNE_MATRIX.replace("[a]", "[a, b, c]"));
+        cmd.assert().success().stderr(
+            "Updated out/b.txt\n\
+             Created out/c.txt\n\
+             Removed out/stale.txt\n\
+             Update finished: 1 updated, 1 created, 1 removed\n",
+        );
+
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).ok();
+        assert_eq!(read("out/b.txt").as_deref(), Some("b\n"));
+        assert_eq!(read("out/c.txt").as_deref(), Some("c\n"));
+        assert_eq!(read("out/stale.txt"), None);
+    }
+
+    /// Test that update mode writes the example in single-output mode
+    #[test]
+    fn test_update_single() {
+        let dir = tempfile::tempdir().expect("tempdir can be created");
+        let example = dir.path().join("example.txt");
+
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.args(["-", "-f", "mocha", "--update", "--check"])
+            .arg(&example)
+            .write_stdin("{{ red.hex }}");
+        cmd.assert()
+            .success()
+            .stderr(predicate::str::contains("Created "));
+        assert_eq!(
+            std::fs::read_to_string(&example).expect("example is written"),
+            "f38ba8"
+        );
+
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.args(["-", "-f", "mocha", "--update", "--check"])
+            .arg(&example)
+            .write_stdin("{{ red.hex }}");
+        cmd.assert().success().stderr("All files are up to date\n");
+    }
+
+    /// Test that check mode can write a JSON summary
+    #[test]
+    fn test_check_summary() {
+        let dir = tempfile::tempdir().expect("tempdir can be created");
+        std::fs::create_dir(dir.path().join("out")).expect("out dir can be created");
+        std::fs::write(dir.path().join("out/a.txt"), "a\n").expect("output can be written");
+
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        let output = cmd
+            .current_dir(dir.path())
+            .args(["-", "--check", "--check-summary", "-"])
+            .write_stdin(PRUNE_MATRIX)
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        let summary: serde_json::Value =
+            serde_json::from_slice(&output).expect("summary is valid JSON");
+        assert_eq!(
+            summary,
+            serde_json::json!({
+                "passed": true,
+                "files": [{ "path": "out/a.txt", "status": "match" }],
+            })
+        );
+    }
+
+    /// Test that the CLI can render every template matching a glob
+    #[test]
+    fn test_template_glob() {
+        let dir = tempfile::tempdir().expect("tempdir can be created");
+        for port in ["a", "b"] {
+            std::fs::write(
+                dir.path().join(format!("{port}.tera")),
+                PRUNE_MATRIX.replace("out/", &format!("out/{port}-")),
+            )
+            .expect("template can be written");
+        }
+
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.current_dir(dir.path())
+            .args(["*.tera", "--dry-run"])
+            .assert()
+            .success()
+            .stdout(
+                "Would write 2 bytes into out/a-a.txt\n\
+                 Would write 2 bytes into out/b-a.txt\n",
+            );
+    }
+
+    /// Test that the CLI can render the templates listed in a batch file
+    #[test]
+    fn test_batch_file() {
+        let dir = tempfile::tempdir().expect("tempdir can be created");
+        std::fs::create_dir(dir.path().join("ports")).expect("ports dir can be created");
+        std::fs::write(dir.path().join("ports/port.tera"), PRUNE_MATRIX)
+            .expect("template can be written");
+        std::fs::write(
+            dir.path().join("batch.json"),
+            r#"{ "templates": ["ports/*.tera"] }"#,
+        )
+        .expect("batch file can be written");
+
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.current_dir(dir.path())
+            .args(["--batch", "batch.json"])
+            .assert()
+            .success();
+        assert!(dir.path().join("out/a.txt").exists());
+    }
+
+    /// Test that a configuration file in a parent directory sets defaults
+    #[test]
+    fn test_config() {
+        let dir = tempfile::tempdir().expect("tempdir can be created");
+        std::fs::create_dir(dir.path().join("ports")).expect("ports dir can be created");
+        std::fs::write(
+            dir.path().join("whiskers.toml"),
+            r##"
+            flavor = "latte"
+            hex_prefix = "#"
+            templates = ["ports/*.tera"]
+            overrides = { greeting = { text = "hello", punctuation = "!" } }
+            "##,
+        )
+        .expect("config can be written");
+        std::fs::write(
+            dir.path().join("ports/port.tera"),
+            "{{ greeting.text }}{{ greeting.punctuation }} {{ red.hex }}",
+        )
+        .expect("template can be written");
+
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.current_dir(dir.path().join("ports"))
+            .args(["--overrides", r#"{"greeting": {"text": "hi"}}"#])
+            .assert()
+            .success()
+            .stdout("hi! #d20f39");
+    }
+
+    /// Test that the configuration file also applies to the test subcommand
+    #[test]
+    fn test_config_applies_to_tests() {
+        let dir = tempfile::tempdir().expect("tempdir can be created");
+        std::fs::write(
+            dir.path().join("whiskers.toml"),
+            "flavor = \"latte\"\nhex_prefix = \"#\"\n",
+        )
+        .expect("config can be written");
+        std::fs::write(
+            dir.path().join("port.tera"),
+            "---\nwhiskers:\n  tests:\n    - contains: \"#d20f39\"\n---\n{{ red.hex }}",
+        )
+        .expect("template can be written");
+
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.current_dir(dir.path())
+            .args(["test", "port.tera"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("... ok"));
+    }
+
+    /// Test that the CLI can render a template using partials from its frontmatter
+    #[test]
+    fn test_partials() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        let assert = cmd
+            .args(["tests/fixtures/partials/partials.tera", "-f", "latte"])
+            .assert();
+        assert
+            .success()
+            .stdout(include_str!("fixtures/partials/partials.md"));
+    }
+
+    /// Test that the CLI can load partials from the --partials flag
+    #[test]
+    fn test_partials_flag() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        let assert = cmd
+            .args(["-", "-f", "mocha"])
+            .args(["--partials", "tests/fixtures/partials/partials"])
+            .write_stdin(r#"{% include "header.tera" %}"#)
+            .assert();
+        assert
+            .success()
+            .stdout(predicate::str::contains("# Catppuccin Mocha"));
+    }
+
+    /// Test that the CLI can render a custom palette from --palette
+    #[test]
+    fn test_palette() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        let assert = cmd
+            .args(["tests/fixtures/palette/palette.tera"])
+            .args(["--palette", "tests/fixtures/palette/palette.yaml"])
+            .assert();
+        assert
+            .success()
+            .stdout(include_str!("fixtures/palette/palette.md"));
+    }
+
+    /// Test that flavors from a custom palette are part of the flavor matrix
+    #[test]
+    fn test_palette_matrix() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        let assert = cmd
+            .args(["--dry-run", "tests/fixtures/palette/matrix.tera"])
+            .args(["--palette", "tests/fixtures/palette/palette.yaml"])
+            .assert();
+        assert
+            .success()
+            .stdout(predicate::str::contains("palette-mocha.txt"))
+            .stdout(predicate::str::contains("palette-midnight.txt"));
+    }
+
+    /// Test that a flavor from a custom palette can be selected with --flavor
+    #[test]
+    fn test_palette_flavor() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        let assert = cmd
+            .args(["-", "-f", "midnight"])
+            .args(["--palette", "tests/fixtures/palette/palette.yaml"])
+            .args(["--color-overrides", r#"{"midnight": {"glow": "ff0000"}}"#])
+            .write_stdin("{{ flavor.name }}: {{ glow.hex }}")
+            .assert();
+        assert.success().stdout("Midnight: ff0000");
+    }
+
+    /// Test that the stock palette passes the contrast audit on base colors
+    #[test]
+    fn test_audit() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        let assert = cmd
+            .args(["audit", "-o", "json", "--background", "base,mantle,crust"])
+            .assert();
+        assert
+            .success()
+            .stdout(predicate::str::contains(r#""mocha": []"#));
+    }
+
+    /// Test that the language server reports syntax errors in open templates
+    #[test]
+    fn test_lsp() {
+        let messages = [
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"capabilities":{}}}"#,
+            r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#,
+            r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"file:///tmp/a.tera","languageId":"tera","version":1,"text":"{{ red. }}"}}}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#,
+            r#"{"jsonrpc":"2.0","method":"exit"}"#,
+        ];
+        let input = messages
+            .map(|message| format!("Content-Length: {}\r\n\r\n{message}", message.len()))
+            .concat();
+
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        let assert = cmd.arg("lsp").write_stdin(input).assert();
+        assert
+            .success()
+            .stdout(predicate::str::contains(r#""colorProvider":true"#))
+            .stdout(predicate::str::contains(
+                r#""message":"Template is invalid: Failed to parse 'template'"#,
+            ));
+    }
+
+    /// Test that valid templates pass validation, including their partials
+    #[test]
+    fn test_validate() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        let assert = cmd
+            .arg("validate")
+            .args(["tests/fixtures/multi/multi.tera"])
+            .args(["tests/fixtures/partials/partials.tera"])
+            .assert();
+        assert.success().stderr("");
+    }
+
+    /// Test that the tests declared in a template's frontmatter pass
+    #[test]
+    fn test_test() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        let assert = cmd
+            .args(["test", "tests/fixtures/tests/tests.tera"])
+            .assert();
+        assert
+            .success()
+            .stdout(predicate::str::contains(
+                "test tests/fixtures/tests/tests.tera: flavor=mocha, accent=mauve ... ok",
+            ))
+            .stdout(predicate::str::contains(
+                "test result: ok. 2 passed; 0 failed",
+            ));
+    }
+
+    /// Test that the CLI can preview a template with a color vision deficiency
+    #[test]
+    fn test_simulate() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        let assert = cmd
+            .args(["-", "-f", "latte", "--simulate", "deuteranopia"])
+            .write_stdin("{{ red.hex }}")
+            .assert();
+        assert.success().stdout("847732");
+    }
+
     /// Test that the CLI can render a UTF-8 template file
     #[test]
     fn test_utf8() {
@@ -76,12 +623,45 @@ mod happy_path {
             .success()
             .stdout(predicate::str::contains("it worked!"));
     }
+
+    /// Test that the CLI can write UTF-16 LE output with a BOM and CRLF line endings
+    #[test]
+    fn test_utf16le_output() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        let assert = cmd
+            .args([
+                "tests/fixtures/encodings/utf16le-output.tera",
+                "-f",
+                "mocha",
+            ])
+            .assert();
+        assert
+            .success()
+            .stdout(&include_bytes!("fixtures/encodings/utf16le-output.reg")[..]);
+    }
+
+    /// Test that check mode compares encoded output
+    #[test]
+    fn test_utf16le_output_check() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        let assert = cmd
+            .args([
+                "tests/fixtures/encodings/utf16le-output.tera",
+                "-f",
+                "mocha",
+            ])
+            .args(["--check", "tests/fixtures/encodings/utf16le-output.reg"])
+            .assert();
+        assert.success();
+    }
 }
 
 #[cfg(test)]
 mod sad_path {
     use assert_cmd::Command;
-    use predicates::prelude::predicate;
+    use predicates::prelude::{predicate, PredicateBooleanExt as _};
+
+    use super::{ACCENT_MATRIX, PRUNE_MATRIX};
 
     #[test]
     fn nonexistent_template_file() {
@@ -92,6 +672,170 @@ mod sad_path {
             .stderr(predicate::str::contains("Failed to open template file"));
     }
 
+    #[test]
+    fn color_overrides_for_unknown_flavor() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.args(["-", "--color-overrides", r#"{"moch": {"base": "000000"}}"#])
+            .write_stdin("{{ base.hex }}");
+        cmd.assert().failure().stderr(predicate::str::contains(
+            "Color overrides name flavor moch, which is not in the palette",
+        ));
+    }
+
+    #[test]
+    fn newer_template_with_unknown_option() {
+        let dir = tempfile::tempdir().expect("tempdir can be created");
+        let template = dir.path().join("theme.tera");
+        std::fs::write(
+            &template,
+            "---\nwhiskers:\n  version: \"^99\"\n  from_the_future: true\n---\n",
+        )
+        .expect("template can be written");
+
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.arg(&template);
+        cmd.assert().failure().stderr(predicate::str::contains(
+            "Template requires whiskers version ^99",
+        ));
+    }
+
+    #[test]
+    fn manifest_in_single_output_mode() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.args(["-", "--manifest", "manifest.json"])
+            .write_stdin("hello");
+        cmd.assert().failure().stderr(predicate::str::contains(
+            "--manifest requires a template with a matrix",
+        ));
+    }
+
+    #[test]
+    fn prune_in_check_mode() {
+        let dir = tempfile::tempdir().expect("tempdir can be created");
+        std::fs::create_dir(dir.path().join("out")).expect("out dir can be created");
+        std::fs::write(dir.path().join("out/a.txt"), "a\n").expect("output can be written");
+        std::fs::write(dir.path().join("out/stale.txt"), "").expect("stale file can be written");
+
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.current_dir(dir.path())
+            .args(["-", "--check", "--prune=out/*.txt"])
+            .write_stdin(PRUNE_MATRIX);
+        cmd.assert().failure().stderr(predicate::str::contains(
+            "out/stale.txt is no longer generated",
+        ));
+        assert!(dir.path().join("out/stale.txt").exists());
+    }
+
+    #[test]
+    fn check_reports_every_file() {
+        let dir = tempfile::tempdir().expect("tempdir can be created");
+        std::fs::create_dir(dir.path().join("out")).expect("out dir can be created");
+        std::fs::write(dir.path().join("out/a.txt"), "A\n").expect("output can be written");
+
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.current_dir(dir.path())
+            .env_remove("DIFFTOOL")
+            .args(["-", "--check"])
+            .write_stdin(PRUNE_MATRIX.replace("[a]", "[a, b]"));
+        cmd.assert().failure().stderr(
+            "Output does not match out/a.txt\n\
+             --- out/a.txt\n\
+             +++ rendered output\n\
+             @@ -1 +1 @@\n\
+             -A\n\
+             +a\n\
+             out/b.txt is missing\n\
+             Check failed: 1 mismatched, 1 missing, 0 stale\n",
+        );
+    }
+
+    #[test]
+    fn batch_render_error() {
+        let dir = tempfile::tempdir().expect("tempdir can be created");
+        std::fs::write(dir.path().join("bad.tera"), "{{ missing }}")
+            .expect("template can be written");
+        std::fs::write(dir.path().join("good.tera"), PRUNE_MATRIX)
+            .expect("template can be written");
+
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.current_dir(dir.path())
+            .args(["bad.tera", "good.tera", "--dry-run"])
+            .assert()
+            .failure()
+            .stdout("Would write 2 bytes into out/a.txt\n")
+            .stderr(predicate::str::contains("Failed to render bad.tera"))
+            .stderr(predicate::str::contains(
+                "1 of 2 templates failed to render",
+            ));
+    }
+
+    #[test]
+    fn config_with_unknown_key() {
+        let dir = tempfile::tempdir().expect("tempdir can be created");
+        std::fs::write(dir.path().join(".whiskersrc"), "flavour = \"mocha\"")
+            .expect("config can be written");
+
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.current_dir(dir.path())
+            .args(["-"])
+            .write_stdin("hello")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "Failed to parse config file .whiskersrc",
+            ))
+            .stderr(predicate::str::contains("unknown field `flavour`"));
+    }
+
+    #[test]
+    fn prune_without_glob_or_manifest() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.args(["-", "--dry-run", "--prune"])
+            .write_stdin(PRUNE_MATRIX);
+        cmd.assert()
+            .failure()
+            .stdout("")
+            .stderr(predicate::str::contains(
+                "--prune requires a glob or --manifest",
+            ));
+    }
+
+    #[test]
+    fn parallel_render_error() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.args(["-", "--dry-run", "--jobs", "4"])
+            .write_stdin(format!("{ACCENT_MATRIX}{{{{ missing }}}}"));
+        cmd.assert()
+            .failure()
+            .stdout("")
+            .stderr(predicate::str::contains(
+                "= note: while rendering matrix combination accent=rosewater",
+            ));
+    }
+
+    #[test]
+    fn matrix_exclude_unknown_key() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.args(["-", "--dry-run"])
+            .write_stdin(ACCENT_MATRIX.replace(
+                "    - accent\n",
+                "    - accent\n    - exclude: [{ flavor: latte }]\n",
+            ));
+        cmd.assert().failure().stderr(predicate::str::contains(
+            "Matrix exclude entry refers to flavor, which is not in the matrix",
+        ));
+    }
+
+    #[test]
+    fn matrix_reserved_key() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.args(["-", "--dry-run"])
+            .write_stdin(ACCENT_MATRIX.replace("---\n{{", "matrix: dark\n---\n{{"));
+        cmd.assert().failure().stderr(predicate::str::contains(
+            "Frontmatter key `matrix` is reserved for the matrix context variable",
+        ));
+    }
+
     #[test]
     fn invalid_flavor() {
         let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
@@ -102,6 +846,68 @@ mod sad_path {
         ));
     }
 
+    #[test]
+    fn watch_template_from_stdin() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.args(["-", "--watch"]).write_stdin("hello");
+        cmd.assert().failure().stderr(predicate::str::contains(
+            "--watch requires a template file, not stdin",
+        ));
+    }
+
+    #[test]
+    fn audit_with_unreadable_override() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.args(["audit", "--flavor", "mocha", "-o", "markdown-table"])
+            .args(["--color-overrides", r#"{"mocha": {"text": "585b70"}}"#]);
+        cmd.assert()
+            .failure()
+            .stdout(predicate::str::contains("| `text` | `base` | 2.46 |"));
+    }
+
+    #[test]
+    fn audit_indistinguishable_under_simulation() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.args(["audit", "--flavor", "latte", "--simulate", "deuteranopia"])
+            .args(["--distinguish", "red,green", "--background", "base"]);
+        cmd.assert()
+            .failure()
+            .stdout(predicate::str::contains(
+                "`red` and `green` are hard to tell apart: ΔE 0.062",
+            ))
+            .stdout(predicate::str::contains("All pairs pass.").not());
+    }
+
+    #[test]
+    fn audit_checks_surfaces_by_default() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.args(["audit", "--flavor", "mocha"]);
+        cmd.assert()
+            .failure()
+            .stdout(predicate::str::contains("`subtext1` on `surface2`: 3.77"));
+    }
+
+    #[test]
+    fn palette_with_invalid_hex() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.args(["tests/fixtures/palette/palette.tera"])
+            .args(["--palette", "tests/fixtures/palette/invalid.json"]);
+        cmd.assert().failure().stderr(predicate::str::contains(
+            "Color midnight.text has invalid hex code e0e0f",
+        ));
+    }
+
+    #[test]
+    fn palette_missing_flavor() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.args(["-", "-f", "latte"])
+            .args(["--palette", "tests/fixtures/palette/palette.yaml"])
+            .write_stdin("{{ red.hex }}");
+        cmd.assert().failure().stderr(predicate::str::contains(
+            "[possible values: mocha, midnight]",
+        ));
+    }
+
     #[test]
     fn template_contains_invalid_syntax() {
         let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
@@ -110,4 +916,55 @@ mod sad_path {
             .failure()
             .stderr(predicate::str::contains("Error: Template is invalid"));
     }
+
+    #[test]
+    fn validate_reports_locations() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.args(["validate", "-"]).write_stdin(
+            "---\nwhiskers:\n  version: \"2\"\n  matrix:\n    - flavour\n---\n{{ red.hex\n",
+        );
+        cmd.assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "<stdin>:5:7: whiskers.matrix[0]: unknown variant `flavour`",
+            ))
+            .stderr(predicate::str::contains("Validation failed: 1 problem"));
+    }
+
+    #[test]
+    fn render_error_points_at_template() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.args(["-", "-f", "mocha"]).write_stdin(
+            "---\nwhiskers:\n  version: \"2\"\n---\n{{ flavor.name }}\n{{ red.hex | nope }}\n",
+        );
+        cmd.assert().failure().stderr(predicate::str::contains(
+            "Error: Template render failed: Filter 'nope' not found\n \
+             --> <stdin>:6:14\n  |\n6 | {{ red.hex | nope }}\n  |              ^^^^",
+        ));
+    }
+
+    #[test]
+    fn failing_template_test() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.args(["test", "-"]).write_stdin(
+            "---\nwhiskers:\n  tests:\n    - flavor: mocha\n      contains: latte\n---\n{{ flavor.name }}",
+        );
+        cmd.assert()
+            .failure()
+            .stdout(predicate::str::contains(
+                "test <stdin>: flavor=mocha ... FAILED\n    Output doesn't contain `latte`",
+            ))
+            .stdout(predicate::str::contains(
+                "test result: FAILED. 0 passed; 1 failed",
+            ));
+    }
+
+    #[test]
+    fn update_without_check() {
+        let mut cmd = Command::cargo_bin("whiskers").expect("binary exists");
+        cmd.args(["-", "--update"]).write_stdin("{{ red.hex }}");
+        cmd.assert().failure().stderr(predicate::str::contains(
+            "the following required arguments were not provided:\n  --check",
+        ));
+    }
 }
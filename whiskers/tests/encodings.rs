@@ -34,3 +34,18 @@ fn utf16le() {
         "fixtures/encodings/utf16le.tera needs to be re-encoded to UTF-16 LE"
     );
 }
+
+#[test]
+fn utf16le_output() {
+    let bytes = &include_bytes!("fixtures/encodings/utf16le-output.reg")[..4];
+    assert_eq!(
+        bytes, b"\xFF\xFEW\x00",
+        "fixtures/encodings/utf16le-output.reg needs to be re-encoded to UTF-16 LE with BOM"
+    );
+    assert!(
+        include_bytes!("fixtures/encodings/utf16le-output.reg")
+            .windows(4)
+            .any(|w| w == b"\r\x00\n\x00"),
+        "fixtures/encodings/utf16le-output.reg needs to use CRLF line endings"
+    );
+}